@@ -0,0 +1,130 @@
+use nyx::{
+    maze,
+    maze_exit::{self, ExitPlacement},
+    maze_generators::{self, MazeGenerator, RecursiveBacktracker},
+    maze_io,
+    maze_mask::{self, MazeMask},
+    maze_metrics::MazeMetrics,
+};
+
+// generates one fixed size maze without opening a window
+// build: cargo build --no-default-features --bin maze_gen, no macroquad so no window or alsa
+// usage: maze_gen [--seed <u64>] [--size <tiles> | --mask <mask.png>] [--algorithm <name>]
+//                 [--format <ascii|txt|png>] [--out <path>]
+// ascii goes to stdout, txt and png are written to --out, metrics always go to stderr
+
+const DEFAULT_SIZE: u32 = 41;
+const MIN_SIZE: u32 = 5;
+
+fn arg_value(name: &str) -> Option<String> {
+    let args: Vec<String> = std::env::args().collect();
+    args.iter()
+        .position(|arg| arg == name)
+        .map(|i| args.get(i + 1).cloned().unwrap_or_default())
+}
+
+fn parse_seed() -> u64 {
+    match arg_value("--seed") {
+        Some(seed) => seed.parse().expect("--seed expects an unsigned integer"),
+        None => instant::now() as u64,
+    }
+}
+
+fn parse_size() -> u32 {
+    match arg_value("--size") {
+        Some(size) => size
+            .parse()
+            .ok()
+            .filter(|size| *size >= MIN_SIZE)
+            .unwrap_or_else(|| panic!("--size expects an integer of at least {}", MIN_SIZE)),
+        None => DEFAULT_SIZE,
+    }
+}
+
+fn parse_mask() -> Option<MazeMask> {
+    arg_value("--mask").map(|path| {
+        if arg_value("--size").is_some() {
+            panic!("--mask sets the size, it can't be used with --size");
+        }
+        std::fs::read(&path)
+            .map_err(|err| maze_io::MazeFileError::Io(err.to_string()))
+            .and_then(|bytes| MazeMask::from_png(&bytes))
+            .unwrap_or_else(|err| panic!("can't load mask '{}': {}", path, err))
+    })
+}
+
+fn parse_generator() -> Box<dyn MazeGenerator> {
+    match arg_value("--algorithm") {
+        Some(name) => maze_generators::parse_generator(&name)
+            .unwrap_or_else(|| panic!("unknown --algorithm '{}'", name)),
+        None => Box::new(RecursiveBacktracker),
+    }
+}
+
+fn parse_output() -> Option<String> {
+    // return: the file to write, None for ascii on stdout
+    let out = arg_value("--out");
+    match arg_value("--format").as_deref() {
+        Some("ascii") => {
+            if out.is_some() {
+                panic!("--format ascii prints to stdout, use txt to write a file");
+            }
+            None
+        }
+        Some(format @ ("txt" | "png")) => {
+            let path = out.unwrap_or_else(|| format!("maze.{}", format));
+            if !path.ends_with(&format!(".{}", format)) {
+                panic!("--format {} expects --out to end in .{}", format, format);
+            }
+            Some(path)
+        }
+        Some(format) => panic!("unknown --format '{}', use ascii, txt or png", format),
+        None => out, // the extension picks the format
+    }
+}
+
+fn main() {
+    let seed = parse_seed();
+    let size = parse_size();
+    let generator = parse_generator();
+    let mask = parse_mask();
+    let output = parse_output();
+
+    let (grid, start) = match &mask {
+        Some(mask) => maze_mask::create_masked_maze(mask, seed, generator.as_ref()),
+        None => {
+            // the start is the cell nearest the middle, cells sit on odd tiles
+            let start = glam::IVec2::splat((size as i32 / 2) | 1);
+            let grid =
+                maze::create_maze_map(size as f32, start.as_vec2(), seed, generator.as_ref());
+            (grid, start)
+        }
+    };
+    let exit = maze_exit::place_exit(
+        &grid,
+        start,
+        ExitPlacement::Farthest,
+        &maze::create_rng(seed),
+    )
+    .unwrap();
+
+    match output {
+        Some(path) => {
+            maze_io::save_maze(&grid, &path)
+                .unwrap_or_else(|err| panic!("can't save maze to '{}': {}", path, err));
+            eprintln!("saved maze to {}", path);
+        }
+        None => print!("{}", maze_io::to_ascii(&grid)),
+    }
+
+    eprintln!(
+        "{} {}x{} seed {}",
+        generator.name(),
+        grid.width(),
+        grid.height(),
+        seed
+    );
+    eprintln!("start:            {}", start);
+    eprintln!("exit:             {}", exit);
+    eprintln!("{}", MazeMetrics::new(&grid, start, exit).unwrap());
+}
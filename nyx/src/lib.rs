@@ -0,0 +1,30 @@
+#[cfg(feature = "graphics")]
+pub mod camera_manager;
+#[cfg(feature = "graphics")]
+pub mod light;
+pub mod light_modes;
+#[cfg(feature = "graphics")]
+pub mod lighting;
+pub mod maze;
+#[cfg(feature = "graphics")]
+pub mod maze_atlas;
+pub mod maze_braid;
+pub mod maze_chunks;
+#[cfg(feature = "graphics")]
+pub mod maze_decor;
+pub mod maze_doors;
+pub mod maze_exit;
+pub mod maze_floors;
+pub mod maze_fov;
+pub mod maze_generators;
+pub mod maze_io;
+pub mod maze_mask;
+pub mod maze_metrics;
+pub mod maze_paths;
+pub mod maze_rooms;
+pub mod maze_shift;
+pub mod maze_topology;
+#[cfg(feature = "graphics")]
+pub mod player;
+pub mod toggle;
+// pub mod util;
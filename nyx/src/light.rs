@@ -2,22 +2,57 @@ use crate::light_modes::LightMode;
 use macroquad::prelude as mq;
 
 #[derive(Clone, Copy)]
+pub struct Falloff {
+    // radii are in px per unit of power, so they grow and shrink with the light mode
+    pub inner: f32, // fully lit inside this
+    pub outer: f32, // dark past this, dithered in between
+    pub curve: f32, // 1 is even, above 1 holds the light longer before it fades
+}
+impl Default for Falloff {
+    fn default() -> Falloff {
+        Falloff::new(4., 16., 1.)
+    }
+}
+impl Falloff {
+    pub fn new(inner: f32, outer: f32, curve: f32) -> Falloff {
+        Falloff {
+            inner: inner.max(0.),
+            outer: outer.max(inner),
+            curve: curve.max(0.01),
+        }
+    }
+}
+
+#[derive(Clone)]
 pub struct Light {
     pub pt: mq::Vec2,
     pub power: f32,
     pub light_mode: LightMode,
     pub color: mq::Color,
+    pub falloff: Falloff,
 }
 impl Light {
-    pub fn new(pt: mq::Vec2, power: f32, light_mode: LightMode, color: mq::Color) -> Light {
+    pub fn new(
+        pt: mq::Vec2,
+        power: f32,
+        light_mode: LightMode,
+        color: mq::Color,
+        falloff: Falloff,
+    ) -> Light {
         Light {
             pt,
             power,
             light_mode,
             color,
+            falloff,
         }
     }
     pub fn calc_power(&self, time: f32) -> f32 {
-        self.power + self.light_mode.calc_power_offset(time)
+        // a light can go out but not below
+        (self.power + self.light_mode.calc_power_offset(time)).max(0.)
+    }
+    pub fn reach(&self) -> f32 {
+        // px, ignoring the light mode
+        self.power * self.falloff.outer
     }
 }
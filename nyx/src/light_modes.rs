@@ -1,7 +1,12 @@
-#[derive(Clone, Copy)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum LightMode {
     Static,
-    Sin(f32, f32, f32), // (power, variance, period, start_time)
+    Sin(f32, f32, f32),                // (variance, period, start_time)
+    Flicker(f32, f32, u64),            // (variance, changes per second, seed)
+    Pulse(f32, f32, f32, f32, f32),    // (amount, period, attack, decay, start_time)
+    Strobe(f32, f32, f32, f32),        // (amount, period, fraction of the period on, start_time)
+    FadeOut(f32, f32, f32), // (amount, lifetime, start_time), amount is usually the power
+    Curve(Vec<(f32, f32)>, bool, f32), // (keys of (time, offset) in order, loops, start_time)
 }
 impl LightMode {
     pub fn calc_power_offset(&self, time: f32) -> f32 {
@@ -10,6 +15,170 @@ impl LightMode {
             LightMode::Sin(variance, period, start_time) => {
                 variance * (((2. * std::f32::consts::PI) / period) * (time - start_time)).sin()
             }
+            LightMode::Flicker(variance, speed, seed) => {
+                // value noise, eased between a new random value each step
+                let x = time * speed;
+                let step = x.floor();
+                let t = x - step;
+                let (a, b) = (noise(*seed, step as i64), noise(*seed, step as i64 + 1));
+                variance * (a + (b - a) * t * t * (3. - 2. * t))
+            }
+            LightMode::Pulse(amount, period, attack, decay, start_time) => {
+                // up over attack, back down over decay, then nothing until the next period
+                let t = (time - start_time).rem_euclid(*period);
+                if t < *attack {
+                    amount * t / attack
+                } else if t < attack + decay {
+                    amount * (1. - (t - attack) / decay)
+                } else {
+                    0.
+                }
+            }
+            LightMode::Strobe(amount, period, on, start_time) => {
+                if (time - start_time).rem_euclid(*period) < on * period {
+                    *amount
+                } else {
+                    0.
+                }
+            }
+            LightMode::FadeOut(amount, lifetime, start_time) => {
+                -amount * ((time - start_time) / lifetime).clamp(0., 1.)
+            }
+            LightMode::Curve(keys, loops, start_time) => {
+                let last = keys.last().map_or(0., |(key_time, _)| *key_time);
+                let mut t = time - start_time;
+                if *loops && last > 0. {
+                    t = t.rem_euclid(last);
+                }
+                // holds the first and last offsets past the ends
+                match keys.iter().position(|(key_time, _)| *key_time > t) {
+                    None => keys.last().map_or(0., |(_, offset)| *offset),
+                    Some(0) => keys[0].1,
+                    Some(i) => {
+                        let ((t0, a), (t1, b)) = (keys[i - 1], keys[i]);
+                        a + (b - a) * (t - t0) / (t1 - t0)
+                    }
+                }
+            }
+        }
+    }
+    pub fn parse_curve(s: &str, loops: bool, start_time: f32) -> Option<LightMode> {
+        // usage: <time>:<offset>, one key per line or comma, times in order
+        let keys = s
+            .split([',', '\n'])
+            .map(|key| key.trim())
+            .filter(|key| !key.is_empty())
+            .map(|key| {
+                let (time, offset) = key.split_once(':')?;
+                Some((time.trim().parse().ok()?, offset.trim().parse().ok()?))
+            })
+            .collect::<Option<Vec<(f32, f32)>>>()?;
+        if keys.is_empty() || keys.windows(2).any(|w| w[1].0 <= w[0].0) {
+            return None;
+        }
+        Some(LightMode::Curve(keys, loops, start_time))
+    }
+}
+
+fn noise(seed: u64, step: i64) -> f32 {
+    // splitmix64, return: -1 to 1
+    let mut z = seed
+        .wrapping_add((step as u64).wrapping_mul(0x9E3779B97F4A7C15))
+        .wrapping_add(0x9E3779B97F4A7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+    z ^= z >> 31;
+    (z >> 40) as f32 / (1u64 << 23) as f32 - 1.
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_near(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-4, "{} isn't {}", a, b);
+    }
+
+    #[test]
+    fn static_is_flat() {
+        for time in [0., 1.5, 100.] {
+            assert_near(LightMode::Static.calc_power_offset(time), 0.);
+        }
+    }
+
+    #[test]
+    fn sin_peaks_a_quarter_period_in() {
+        let mode = LightMode::Sin(0.5, 4., 1.);
+        assert_near(mode.calc_power_offset(1.), 0.);
+        assert_near(mode.calc_power_offset(2.), 0.5);
+        assert_near(mode.calc_power_offset(3.), 0.);
+        assert_near(mode.calc_power_offset(4.), -0.5);
+    }
+
+    #[test]
+    fn flicker_is_seeded_and_stays_in_range() {
+        let mode = LightMode::Flicker(0.3, 8., 42);
+        let other = LightMode::Flicker(0.3, 8., 43);
+        let mut differs = false;
+        for i in 0..200 {
+            let time = i as f32 * 0.037;
+            let offset = mode.calc_power_offset(time);
+            assert!(offset.abs() <= 0.3);
+            assert_eq!(offset, mode.clone().calc_power_offset(time));
+            differs |= offset != other.calc_power_offset(time);
         }
+        assert!(differs);
+        // lands on the step's own value on each step
+        assert_near(mode.calc_power_offset(0.5), 0.3 * noise(42, 4));
+    }
+
+    #[test]
+    fn pulse_attacks_then_decays() {
+        let mode = LightMode::Pulse(1., 2., 0.5, 1., 0.);
+        assert_near(mode.calc_power_offset(0.), 0.);
+        assert_near(mode.calc_power_offset(0.25), 0.5);
+        assert_near(mode.calc_power_offset(0.5), 1.);
+        assert_near(mode.calc_power_offset(1.), 0.5);
+        assert_near(mode.calc_power_offset(1.75), 0.);
+        assert_near(mode.calc_power_offset(2.25), 0.5);
+    }
+
+    #[test]
+    fn strobe_is_on_for_its_fraction() {
+        let mode = LightMode::Strobe(2., 1., 0.25, 0.5);
+        assert_near(mode.calc_power_offset(0.6), 2.);
+        assert_near(mode.calc_power_offset(1.), 0.);
+        assert_near(mode.calc_power_offset(1.6), 2.);
+        assert_near(mode.calc_power_offset(0.4), 0.);
+    }
+
+    #[test]
+    fn fade_out_goes_down_by_amount_over_lifetime() {
+        let mode = LightMode::FadeOut(3., 2., 1.);
+        assert_near(mode.calc_power_offset(0.5), 0.);
+        assert_near(mode.calc_power_offset(2.), -1.5);
+        assert_near(mode.calc_power_offset(10.), -3.);
+    }
+
+    #[test]
+    fn curve_follows_keys() {
+        let once = LightMode::parse_curve("0:0, 1:2, 3:0", false, 0.).unwrap();
+        assert_near(once.calc_power_offset(-1.), 0.);
+        assert_near(once.calc_power_offset(0.5), 1.);
+        assert_near(once.calc_power_offset(2.), 1.);
+        assert_near(once.calc_power_offset(10.), 0.);
+
+        let looped = LightMode::parse_curve("0:0\n1:2\n3:0\n", true, 1.).unwrap();
+        assert_near(looped.calc_power_offset(1.5), 1.);
+        assert_near(looped.calc_power_offset(4.5), 1.);
+        assert_near(looped.calc_power_offset(6.), 1.);
+    }
+
+    #[test]
+    fn curve_rejects_bad_data() {
+        assert_eq!(LightMode::parse_curve("", false, 0.), None);
+        assert_eq!(LightMode::parse_curve("0:1, x:2", false, 0.), None);
+        assert_eq!(LightMode::parse_curve("1:0, 1:2", false, 0.), None);
+        assert_eq!(LightMode::parse_curve("0 1", false, 0.), None);
     }
 }
@@ -0,0 +1,747 @@
+use crate::light::Light;
+use crate::maze_fov;
+use crate::maze_paths::Walkable;
+use crate::maze_topology::Segment;
+use macroquad::miniquad::{BlendFactor, BlendState, BlendValue, Equation};
+use macroquad::prelude as mq;
+
+// macroquad can't set uniform arrays, so every light gets its own pair of vec4s, and the light
+// mask keeps a bit per light in its red then green byte
+// desktop gl and most phones have room for all of them
+pub const MAX_LIGHTS: usize = 16;
+// webgl only promises 16 vec4 uniforms in a fragment shader, two per light leaves two for the
+// rest, Lighting::new drops to this many when the full shader doesn't build
+pub const FALLBACK_LIGHTS: usize = 7;
+
+// 4x4 bayer matrix, a pixel past a light's inner radius is lit while its value is ahead of the
+// falloff
+pub const DITHER: [u8; 16] = [0, 8, 2, 10, 12, 4, 14, 6, 3, 11, 1, 9, 15, 7, 13, 5];
+pub const DITHER_SIZE: u32 = 4;
+
+pub fn dither_idx(x: u32, y: u32) -> usize {
+    ((y % DITHER_SIZE) * DITHER_SIZE + (x % DITHER_SIZE)) as usize
+}
+
+// lit floor is rounded to this many steps a channel, dithered so the steps don't band
+pub const PALETTE_STEPS: u32 = 15;
+
+#[derive(PartialEq, Eq, Debug, Copy, Clone)]
+pub enum Tonemap {
+    // how light added up past white is brought back down
+    Clamp,
+    Reinhard, // c / (1 + c), never quite reaches white so bright overlaps keep their hue
+}
+impl Tonemap {
+    pub fn parse(s: &str) -> Option<Tonemap> {
+        // usage: clamp | reinhard
+        match s {
+            "clamp" => Some(Tonemap::Clamp),
+            "reinhard" => Some(Tonemap::Reinhard),
+            _ => None,
+        }
+    }
+    fn glsl(&self) -> &'static str {
+        match self {
+            Tonemap::Clamp => "min(c, vec3(1.))",
+            Tonemap::Reinhard => "c / (1. + c)",
+        }
+    }
+}
+
+const VERTEX_SHADER: &str = r#"#version 100
+attribute vec3 position;
+attribute vec2 texcoord;
+
+varying vec2 uv;
+
+uniform mat4 Model;
+uniform mat4 Projection;
+
+void main() {
+    gl_Position = Projection * Model * vec4(position, 1);
+    uv = texcoord;
+}
+"#;
+
+const FRAGMENT_SHADER: &str = r#"#version 100
+#ifdef GL_FRAGMENT_PRECISION_HIGH
+precision highp float;
+#else
+precision mediump float;
+#endif
+
+varying vec2 uv;
+
+uniform sampler2D Texture;
+uniform sampler2D dither;
+uniform sampler2D light_mask;
+uniform vec4 mask_rect;
+uniform vec2 screen_size;
+uniform int light_count;
+LIGHT_UNIFORMS
+float dither_at(vec2 px) {
+    vec2 size = vec2(DITHER_SIZE);
+    return floor(texture2D(dither, (mod(px, size) + 0.5) / size).r * 255. + 0.5);
+}
+
+bool sees(float mask, float bit) {
+    return mod(floor(mask / bit), 2.) == 1.;
+}
+
+float strength(vec4 light, float curve, vec2 px) {
+    // light.xy is where the light is on screen, light.zw are its inner and outer radii in px
+    // return: 0 past the dithered edge, otherwise how bright the light still is
+    float dist = distance(light.xy, px + 0.5);
+    if (dist < light.z) {
+        return 1.;
+    }
+    if (dist >= light.w) {
+        return 0.;
+    }
+    float t = pow((dist - light.z) / (light.w - light.z), curve);
+    return t * DITHER_LEN <= dither_at(px) ? 1. - t : 0.;
+}
+
+// lights add up in linear space, colors come in and go out as srgb
+vec3 to_linear(vec3 c) {
+    return pow(c, vec3(2.2));
+}
+
+vec3 to_srgb(vec3 c) {
+    return pow(c, vec3(1. / 2.2));
+}
+
+vec3 tonemap(vec3 c) {
+    return TONEMAP;
+}
+
+void add_light(inout vec3 light, inout bool reached, vec4 pt, vec4 color, vec2 px) {
+    float s = strength(pt, color.w, px);
+    light += s * to_linear(color.rgb);
+    reached = reached || s > 0.;
+}
+
+void main() {
+    // drawn with flip_y, so px counts down from the top like the camera
+    vec2 px = floor(vec2(uv.x, 1. - uv.y) * screen_size);
+    vec4 screen = texture2D(Texture, uv);
+    // a bit for each light that can see this tile, the first 8 in red and the rest in green
+    vec2 mask_uv = (px + 0.5 - mask_rect.xy) / mask_rect.zw;
+    vec2 mask = floor(texture2D(light_mask, mask_uv).rg * 255. + 0.5);
+
+    vec3 light = vec3(0.);
+    bool reached = false;
+LIGHT_CHECKS
+    if (!reached) {
+        gl_FragColor = vec4(0., 0., 0., 1.);
+    } else if (screen != vec4(0., 0., 0., 1.)) {
+        // whatever was drawn keeps its own color wherever light reaches it
+        gl_FragColor = screen;
+    } else {
+        vec3 color = to_srgb(tonemap(light));
+        color = floor(color * PALETTE_STEPS + dither_at(px) / DITHER_LEN) / PALETTE_STEPS;
+        gl_FragColor = vec4(color, 1.);
+    }
+}
+"#;
+
+pub fn fragment_shader(tonemap: Tonemap, lights: usize) -> String {
+    // lights: slots in the shader, up to MAX_LIGHTS
+    let uniforms = (0..lights)
+        .map(|i| {
+            format!(
+                "uniform vec4 light_{};\nuniform vec4 light_color_{};\n",
+                i, i
+            )
+        })
+        .collect::<String>();
+    let checks = (0..lights)
+        .map(|i| {
+            format!(
+                "    if ({} < light_count && sees(mask.{}, {:.1})) {{\n        \
+                 add_light(light, reached, light_{}, light_color_{}, px);\n    }}\n",
+                i,
+                ["r", "g"][i / 8],
+                (1 << (i % 8)) as f32,
+                i,
+                i
+            )
+        })
+        .collect::<String>();
+    FRAGMENT_SHADER
+        .replace("LIGHT_UNIFORMS", &uniforms)
+        .replace("LIGHT_CHECKS", &checks)
+        .replace("TONEMAP", tonemap.glsl())
+        .replace("PALETTE_STEPS", &format!("{:.1}", PALETTE_STEPS as f32))
+        .replace("DITHER_SIZE", &format!("{:.1}", DITHER_SIZE as f32))
+        .replace("DITHER_LEN", &format!("{:.1}", DITHER.len() as f32))
+}
+
+pub enum Occluder<'a> {
+    // light only reaches the tiles it can see
+    Tiles {
+        maze: &'a dyn Walkable,
+        maze_pt: mq::Vec2, // where tile (0, 0) is on the screen texture
+        tile_size: f32,
+    },
+    // light stops at wall segments, for mazes that aren't on tiles
+    Walls {
+        walls: &'a [Segment],
+        maze_pt: mq::Vec2, // where (0, 0) of the walls is on the screen texture
+        cell_size: f32,    // px of screen each bit of the mask covers
+    },
+}
+
+fn crosses(from: mq::Vec2, to: mq::Vec2, wall: Segment) -> Option<f32> {
+    // return: how far along from to to the line hits the wall, 0 to 1
+    let (r, s) = (to - from, wall.1 - wall.0);
+    let denom = r.perp_dot(s);
+    if denom == 0. {
+        return None; // parallel
+    }
+    let d = wall.0 - from;
+    let t = d.perp_dot(s) / denom;
+    let u = d.perp_dot(r) / denom;
+    ((0. ..=1.).contains(&t) && (0. ..=1.).contains(&u)).then_some(t)
+}
+
+fn tile_mask(
+    lights: &[Light],
+    maze: &dyn Walkable,
+    maze_pt: mq::Vec2,
+    tile_size: f32,
+    screen_size: mq::Vec2,
+) -> (mq::Image, mq::Rect) {
+    let min = (-maze_pt / tile_size).floor().as_ivec2();
+    let max = ((screen_size - maze_pt) / tile_size).floor().as_ivec2();
+    let size = max - min + 1;
+    let mut image = mq::Image {
+        bytes: [0, 0, 0, 255].repeat((size.x * size.y) as usize),
+        width: size.x as u16,
+        height: size.y as u16,
+    };
+    for (i, light) in lights.iter().take(MAX_LIGHTS).enumerate() {
+        let origin = ((light.pt - maze_pt) / tile_size).floor().as_ivec2();
+        let radius = (light.reach() / tile_size).ceil() as i32 + 1;
+        for tile in maze_fov::field_of_view(maze, origin, radius) {
+            let d = tile - min;
+            if d.x >= 0 && d.y >= 0 && d.x < size.x && d.y < size.y {
+                image.bytes[((d.y * size.x + d.x) * 4) as usize + i / 8] |= 1 << (i % 8);
+            }
+        }
+    }
+    let rect = mq::Rect::new(
+        maze_pt.x + min.x as f32 * tile_size,
+        maze_pt.y + min.y as f32 * tile_size,
+        size.x as f32 * tile_size,
+        size.y as f32 * tile_size,
+    );
+    (image, rect)
+}
+
+fn wall_mask(
+    lights: &[Light],
+    walls: &[Segment],
+    maze_pt: mq::Vec2,
+    cell_size: f32,
+    screen_size: mq::Vec2,
+) -> (mq::Image, mq::Rect) {
+    let size = (screen_size / cell_size).ceil().as_ivec2();
+    let mut image = mq::Image {
+        bytes: [0, 0, 0, 255].repeat((size.x * size.y) as usize),
+        width: size.x as u16,
+        height: size.y as u16,
+    };
+    for (i, light) in lights.iter().take(MAX_LIGHTS).enumerate() {
+        let reach = light.reach() + cell_size;
+        // only walls near enough to the light can cast a shadow inside its reach
+        let near = walls
+            .iter()
+            .map(|(a, b)| (maze_pt + *a, maze_pt + *b))
+            .filter(|(a, b)| {
+                let closest = (light.pt - *a).dot(*b - *a) / (*b - *a).length_squared().max(1.);
+                let closest = *a + (*b - *a) * closest.clamp(0., 1.);
+                closest.distance(light.pt) <= reach
+            })
+            .collect::<Vec<Segment>>();
+        for y in 0..size.y {
+            for x in 0..size.x {
+                let center = (mq::vec2(x as f32, y as f32) + 0.5) * cell_size;
+                let dist = center.distance(light.pt);
+                if dist > reach {
+                    continue;
+                }
+                // a cell the wall runs through is lit from whichever side the light is on
+                let blocked = near.iter().any(|wall| {
+                    crosses(light.pt, center, *wall)
+                        .is_some_and(|t| t * dist < dist - cell_size / 2.)
+                });
+                if !blocked {
+                    image.bytes[((y * size.x + x) * 4) as usize + i / 8] |= 1 << (i % 8);
+                }
+            }
+        }
+    }
+    let rect = mq::Rect::new(0., 0., size.x as f32 * cell_size, size.y as f32 * cell_size);
+    (image, rect)
+}
+
+pub fn light_mask(
+    lights: &[Light],
+    occluder: &Occluder,
+    screen_size: mq::Vec2,
+) -> (mq::Image, mq::Rect) {
+    // lights: same as Lighting::draw
+    // return: one pixel per tile or cell on the screen, bit i of red, or bit i - 8 of green, is
+    // set when light i can see it, and where the pixels go on the screen texture
+    match occluder {
+        Occluder::Tiles {
+            maze,
+            maze_pt,
+            tile_size,
+        } => tile_mask(lights, *maze, *maze_pt, *tile_size, screen_size),
+        Occluder::Walls {
+            walls,
+            maze_pt,
+            cell_size,
+        } => wall_mask(lights, walls, *maze_pt, *cell_size, screen_size),
+    }
+}
+
+pub struct Lighting {
+    material: mq::Material,
+    max_lights: usize,
+    dither: mq::Texture2D,
+    mask: mq::Texture2D,
+}
+impl Lighting {
+    pub fn new(tonemap: Tonemap) -> Result<Lighting, mq::ShaderError> {
+        // needs the window to be open
+        let (material, max_lights) = match Lighting::material(tonemap, MAX_LIGHTS) {
+            Ok(material) => (material, MAX_LIGHTS),
+            Err(err) => {
+                mq::warn!(
+                    "only {} lights fit in the lighting shader: {}",
+                    FALLBACK_LIGHTS,
+                    err
+                );
+                (
+                    Lighting::material(tonemap, FALLBACK_LIGHTS)?,
+                    FALLBACK_LIGHTS,
+                )
+            }
+        };
+
+        // one value per pixel in the red channel, the shader works out where it repeats
+        let mut bytes = vec![];
+        for y in 0..DITHER_SIZE {
+            for x in 0..DITHER_SIZE {
+                bytes.extend([DITHER[dither_idx(x, y)], 0, 0, 255]);
+            }
+        }
+        let dither = mq::Texture2D::from_rgba8(DITHER_SIZE as u16, DITHER_SIZE as u16, &bytes);
+        dither.set_filter(mq::FilterMode::Nearest);
+        Ok(Lighting {
+            material,
+            max_lights,
+            dither,
+            mask: mq::Texture2D::empty(),
+        })
+    }
+    fn material(tonemap: Tonemap, lights: usize) -> Result<mq::Material, mq::ShaderError> {
+        let mut uniforms = vec![
+            ("screen_size".to_owned(), mq::UniformType::Float2),
+            ("light_count".to_owned(), mq::UniformType::Int1),
+            ("mask_rect".to_owned(), mq::UniformType::Float4),
+        ];
+        for i in 0..lights {
+            uniforms.push((format!("light_{}", i), mq::UniformType::Float4));
+            uniforms.push((format!("light_color_{}", i), mq::UniformType::Float4));
+        }
+        mq::load_material(
+            VERTEX_SHADER,
+            &fragment_shader(tonemap, lights),
+            mq::MaterialParams {
+                uniforms,
+                textures: vec!["dither".to_owned(), "light_mask".to_owned()],
+                // blends like macroquad's own material
+                pipeline_params: mq::PipelineParams {
+                    color_blend: Some(BlendState::new(
+                        Equation::Add,
+                        BlendFactor::Value(BlendValue::SourceAlpha),
+                        BlendFactor::OneMinusValue(BlendValue::SourceAlpha),
+                    )),
+                    ..Default::default()
+                },
+            },
+        )
+    }
+    pub fn max_lights(&self) -> usize {
+        // MAX_LIGHTS, or FALLBACK_LIGHTS where the full shader doesn't fit
+        self.max_lights
+    }
+    pub fn draw(
+        &mut self,
+        texture: mq::Texture2D,
+        lights: &[Light],
+        occluder: Option<&Occluder>,
+        dest: mq::Rect,
+    ) {
+        // lights: pt on the screen texture and power already worked out for this frame
+        // past max_lights they're dropped, so put the ones that matter first and warn when it happens
+        // lights are opaque, the color's alpha carries the falloff curve instead
+        // occluder: None lights straight through everything
+        let count = lights.len().min(self.max_lights);
+        let screen_size = mq::vec2(texture.width(), texture.height());
+        let (mask, mask_rect) = match occluder {
+            Some(occluder) => light_mask(&lights[..count], occluder, screen_size),
+            None => (
+                mq::Image::gen_image_color(1, 1, mq::WHITE),
+                mq::Rect::new(0., 0., screen_size.x, screen_size.y),
+            ),
+        };
+        // textures aren't freed on drop, so reuse it until the number of tiles on screen changes
+        if self.mask.width() as u16 == mask.width && self.mask.height() as u16 == mask.height {
+            self.mask.update(&mask);
+        } else {
+            self.mask.delete();
+            self.mask = mq::Texture2D::from_image(&mask);
+            self.mask.set_filter(mq::FilterMode::Nearest);
+        }
+        self.material.set_texture("dither", self.dither);
+        self.material.set_texture("light_mask", self.mask);
+        self.material.set_uniform(
+            "mask_rect",
+            mq::vec4(mask_rect.x, mask_rect.y, mask_rect.w, mask_rect.h),
+        );
+        self.material.set_uniform("screen_size", screen_size);
+        self.material.set_uniform("light_count", count as i32);
+        for (i, light) in lights.iter().take(count).enumerate() {
+            self.material.set_uniform(
+                &format!("light_{}", i),
+                mq::vec4(
+                    light.pt.x,
+                    light.pt.y,
+                    light.falloff.inner * light.power,
+                    light.falloff.outer * light.power,
+                ),
+            );
+            let color = light.color;
+            self.material.set_uniform(
+                &format!("light_color_{}", i),
+                mq::vec4(color.r, color.g, color.b, light.falloff.curve),
+            );
+        }
+
+        mq::gl_use_material(self.material);
+        mq::draw_texture_ex(
+            texture,
+            dest.x,
+            dest.y,
+            mq::WHITE,
+            mq::DrawTextureParams {
+                dest_size: Some(dest.size()),
+                flip_y: true,
+                ..Default::default()
+            },
+        );
+        mq::gl_use_default_material();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::light::Falloff;
+    use crate::light_modes::LightMode;
+    use crate::{maze, maze_generators};
+
+    use std::fmt::Write;
+
+    const WIDTH: u32 = 256;
+    const HEIGHT: u32 = 144;
+    const BLACK: mq::Color = mq::Color::new(0., 0., 0., 1.);
+
+    fn light(pt: mq::Vec2, power: f32, color: [u8; 3], falloff: Falloff) -> Light {
+        let [r, g, b] = color;
+        Light::new(
+            pt,
+            power,
+            LightMode::Static,
+            mq::Color::from_rgba(r, g, b, 255),
+            falloff,
+        )
+    }
+
+    fn reference(
+        screen: &mq::Image,
+        lights: &[Light],
+        mask: &(mq::Image, mq::Rect),
+        tonemap: Tonemap,
+    ) -> mq::Image {
+        // the fragment shader pixel for pixel, rows from the top like the screen
+        let (mask, rect) = mask;
+        let mut image = mq::Image::gen_image_color(WIDTH as u16, HEIGHT as u16, BLACK);
+        for y in 0..HEIGHT {
+            for x in 0..WIDTH {
+                let center = mq::vec2(x as f32, y as f32) + 0.5;
+                let dither = DITHER[dither_idx(x, y)] as f32;
+                let cell = ((center - rect.point()) / rect.size()
+                    * mq::vec2(mask.width as f32, mask.height as f32))
+                .floor();
+                let px = (cell.y as usize * mask.width as usize + cell.x as usize) * 4;
+                let bits = u16::from_le_bytes([mask.bytes[px], mask.bytes[px + 1]]);
+                let mut sum = mq::Vec3::ZERO;
+                let mut reached = false;
+                for (i, light) in lights.iter().take(MAX_LIGHTS).enumerate() {
+                    if bits & (1 << i) == 0 {
+                        continue;
+                    }
+                    let falloff = light.falloff;
+                    let (inner, outer) = (falloff.inner * light.power, falloff.outer * light.power);
+                    let dist = light.pt.distance(center);
+                    let s = if dist < inner {
+                        1.
+                    } else if dist >= outer {
+                        0.
+                    } else {
+                        let t = ((dist - inner) / (outer - inner)).powf(falloff.curve);
+                        if t * DITHER.len() as f32 <= dither {
+                            1. - t
+                        } else {
+                            0.
+                        }
+                    };
+                    let color = light.color;
+                    sum += s * mq::vec3(color.r, color.g, color.b).powf(2.2);
+                    reached = reached || s > 0.;
+                }
+                // the screen texture is drawn flipped
+                let drawn = screen.get_pixel(x, HEIGHT - y - 1);
+                let color = if !reached {
+                    BLACK
+                } else if drawn != BLACK {
+                    drawn
+                } else {
+                    let c = match tonemap {
+                        Tonemap::Clamp => sum.min(mq::Vec3::ONE),
+                        Tonemap::Reinhard => sum / (1. + sum),
+                    };
+                    let steps = PALETTE_STEPS as f32;
+                    let c =
+                        (c.powf(1. / 2.2) * steps + dither / DITHER.len() as f32).floor() / steps;
+                    mq::Color::new(c.x, c.y, c.z, 1.)
+                };
+                // gl rounds to the nearest byte
+                let byte = |v: f32| (v * 255.).round() as u8;
+                let color = mq::Color::from_rgba(byte(color.r), byte(color.g), byte(color.b), 255);
+                image.set_pixel(x, y, color);
+            }
+        }
+        image
+    }
+
+    fn cases() -> Vec<(Vec<Light>, (mq::Image, mq::Rect))> {
+        let screen_size = mq::vec2(WIDTH as f32, HEIGHT as f32);
+        let unmasked = || {
+            (
+                mq::Image::gen_image_color(1, 1, mq::WHITE),
+                mq::Rect::new(0., 0., screen_size.x, screen_size.y),
+            )
+        };
+        let (gold, grey) = ([235, 203, 139], [25, 25, 25]);
+        let grid = maze::create_maze_map(
+            41.,
+            mq::vec2(21., 21.),
+            5,
+            maze_generators::parse_generator("kruskal")
+                .unwrap()
+                .as_ref(),
+        );
+        let walls = [
+            (mq::vec2(20., 10.), mq::vec2(20., 130.)),
+            (mq::vec2(60., 40.), mq::vec2(180., 60.)),
+        ];
+
+        let one = vec![light(mq::vec2(128.3, 72.6), 3.6, grey, Falloff::default())];
+        let two = vec![
+            light(mq::vec2(100., 72.), 3., gold, Falloff::default()),
+            light(mq::vec2(140., 72.), 3.6, grey, Falloff::default()),
+        ];
+        let mixed = vec![
+            light(
+                mq::vec2(-10.5, 40.25),
+                1.2,
+                [200, 40, 60],
+                Falloff::new(2., 30., 2.),
+            ),
+            light(
+                mq::vec2(200., 10.),
+                2.,
+                [30, 180, 20],
+                Falloff::new(0., 20., 0.5),
+            ),
+            light(mq::vec2(120., 72.), 5., gold, Falloff::default()),
+            light(mq::vec2(130., 70.), 4., [255, 255, 255], Falloff::default()),
+        ];
+        // one in every slot, two rows of them
+        let full = (0..MAX_LIGHTS)
+            .map(|i| {
+                let pt = mq::vec2(
+                    16. + 32. * (i % 8) as f32,
+                    30. + 80. * (i / 8) as f32 + i as f32,
+                );
+                light(
+                    pt,
+                    2. + i as f32 / 6.,
+                    [15 * i as u8, 90, 200],
+                    Falloff::default(),
+                )
+            })
+            .collect::<Vec<Light>>();
+        let tiles = Occluder::Tiles {
+            maze: &grid,
+            maze_pt: mq::vec2(-37.3, -21.6),
+            tile_size: 16.,
+        };
+        let shadows = Occluder::Walls {
+            walls: &walls,
+            maze_pt: mq::vec2(3., -2.),
+            cell_size: 4.,
+        };
+        vec![
+            (one, unmasked()),
+            (two, unmasked()),
+            (mixed.clone(), light_mask(&mixed, &tiles, screen_size)),
+            (mixed.clone(), light_mask(&mixed, &shadows, screen_size)),
+            (full.clone(), light_mask(&full, &tiles, screen_size)),
+        ]
+    }
+
+    fn screen() -> mq::Image {
+        // black with the odd bright pixel, those keep their own color where light reaches
+        let rng = maze::create_rng(7);
+        let mut screen = mq::Image::gen_image_color(WIDTH as u16, HEIGHT as u16, BLACK);
+        for y in 0..HEIGHT {
+            for x in 0..WIDTH {
+                if rng.gen_range(0, 8) == 0 {
+                    let c = || rng.gen_range(0u8, 255);
+                    screen.set_pixel(x, y, mq::Color::from_rgba(c(), c(), c(), 255));
+                }
+            }
+        }
+        screen
+    }
+
+    #[test]
+    fn a_slot_for_each_light() {
+        for lights in [MAX_LIGHTS, FALLBACK_LIGHTS] {
+            let shader = fragment_shader(Tonemap::Clamp, lights);
+            for i in 0..lights {
+                assert!(shader.contains(&format!("uniform vec4 light_color_{};", i)));
+            }
+            assert!(!shader.contains(&format!("light_{}", lights)));
+            assert!(!shader.contains("LIGHT_"));
+        }
+        // the ninth light is the first in green
+        let shader = fragment_shader(Tonemap::Clamp, MAX_LIGHTS);
+        assert!(shader.contains("sees(mask.r, 128.0)"));
+        assert!(shader.contains("sees(mask.g, 1.0)"));
+        assert!(!shader.contains("sees(mask.g, 256.0)"));
+    }
+
+    #[test]
+    fn masks_mark_what_each_light_sees() {
+        let cases = cases();
+        let (lights, (mask, rect)) = &cases[2];
+        assert_eq!(lights.len(), 4);
+        let screen_size = mq::vec2(WIDTH as f32, HEIGHT as f32);
+        assert!(rect.x <= 0. && rect.y <= 0. && rect.right() >= screen_size.x);
+        let bits = mask.bytes.chunks(4).fold(0, |bits, px| bits | px[0]);
+        assert_eq!(bits, 0b1111);
+        // the light at (-10.5, 40.25) is off the screen and lights only what it can see
+        let seen = mask.bytes.chunks(4).filter(|px| px[0] & 1 != 0).count();
+        assert!(seen > 0 && seen < mask.bytes.len() / 8);
+
+        let (lights, (mask, _)) = &cases[4];
+        let bits = mask.bytes.chunks(4).fold(0, |bits, px| {
+            bits | u16::from_le_bytes([px[0], px[1]]) as u32
+        });
+        assert_eq!(bits, (1 << lights.len()) - 1);
+    }
+
+    #[test]
+    fn reference_lights_only_what_is_reached() {
+        let cases = cases();
+        let (lights, mask) = &cases[0];
+        let image = reference(&screen(), lights, mask, Tonemap::Clamp);
+        assert_eq!(image.get_pixel(0, 0), BLACK);
+        assert_ne!(image.get_pixel(128, 72), BLACK);
+    }
+
+    #[test]
+    #[ignore]
+    fn write_llvmpipe_cases() {
+        // tools/lighting_check.sh renders these with mesa and compares them with the reference
+        let dir = std::env::var("NYX_LIGHTING_CASES").expect("NYX_LIGHTING_CASES isn't set");
+        let screen = screen();
+        let dither = (0..DITHER_SIZE * DITHER_SIZE)
+            .flat_map(|i| {
+                [
+                    DITHER[dither_idx(i % DITHER_SIZE, i / DITHER_SIZE)],
+                    0,
+                    0,
+                    255,
+                ]
+            })
+            .collect::<Vec<u8>>();
+        for (name, tonemap) in [("clamp", Tonemap::Clamp), ("reinhard", Tonemap::Reinhard)] {
+            let dir = format!("{}/{}", dir, name);
+            let write = |file: &str, bytes: &[u8]| {
+                std::fs::write(format!("{}/{}", dir, file), bytes).unwrap();
+            };
+            std::fs::create_dir_all(&dir).unwrap();
+            write("vert.glsl", VERTEX_SHADER.as_bytes());
+            write("frag.glsl", fragment_shader(tonemap, MAX_LIGHTS).as_bytes());
+            write("dither.rgba", &dither);
+            write("screen.rgba", &screen.bytes);
+            // one line a case, then one line a light, in the order the uniforms are set
+            let mut list = String::new();
+            for (i, (lights, mask)) in cases().iter().enumerate() {
+                let rect = mask.1;
+                writeln!(
+                    list,
+                    "{} {} {} {} {} {} {}",
+                    lights.len(),
+                    mask.0.width,
+                    mask.0.height,
+                    rect.x,
+                    rect.y,
+                    rect.w,
+                    rect.h
+                )
+                .unwrap();
+                for light in lights {
+                    let (falloff, color) = (light.falloff, light.color);
+                    writeln!(
+                        list,
+                        "{} {} {} {} {} {} {} {}",
+                        light.pt.x,
+                        light.pt.y,
+                        falloff.inner * light.power,
+                        falloff.outer * light.power,
+                        color.r,
+                        color.g,
+                        color.b,
+                        falloff.curve
+                    )
+                    .unwrap();
+                }
+                write(&format!("mask{}.rgba", i), &mask.0.bytes);
+                let expected = reference(&screen, lights, mask, tonemap);
+                write(&format!("expected{}.rgba", i), &expected.bytes);
+            }
+            write("cases.txt", list.as_bytes());
+        }
+    }
+}
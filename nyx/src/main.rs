@@ -1,12 +1,26 @@
-mod camera_manager;
-mod light;
-mod light_modes;
-mod maze;
-mod player;
-mod toggle;
-// mod util;
-
-use crate::{camera_manager::CameraManager, light::Light, light_modes::LightMode, player::Player};
+use nyx::{
+    camera_manager::CameraManager,
+    light::{Falloff, Light},
+    light_modes::LightMode,
+    lighting::{Lighting, Occluder, Tonemap},
+    maze,
+    maze::MazeGrid,
+    maze_atlas::{self, AtlasDef, TileAtlas},
+    maze_braid::BraidConfig,
+    maze_chunks::ChunkedMaze,
+    maze_decor::{self, DecorConfig},
+    maze_doors::{self, Lock, Locked},
+    maze_exit::{self, ExitPlacement},
+    maze_floors::{self, MazeFloors},
+    maze_generators::{self, MazeGenerator, RecursiveBacktracker},
+    maze_io,
+    maze_mask::{self, MazeMask},
+    maze_metrics::MazeMetrics,
+    maze_rooms::{RoomConfig, RoomOrder},
+    maze_shift,
+    maze_topology::{HexShape, PolarShape, Segment, Shape, ShapedMaze},
+    player::Player,
+};
 
 use macroquad::prelude as mq;
 
@@ -16,12 +30,26 @@ const PX_HEIGHT: u32 = 144;
 const START_WIDTH: u32 = 1440;
 const START_HEIGHT: u32 = 810;
 
-const MAZE_SIZE: f32 = 101.;
-const MAZE_START: mq::Vec2 = mq::vec2(
-    ((MAZE_SIZE / 2.) as u32) as f32,
-    ((MAZE_SIZE / 2.) as u32) as f32,
-);
+const CHUNK_SIZE: i32 = 16;
+const CHUNK_LOAD_RADIUS: i32 = 1;
+const CHUNK_UNLOAD_RADIUS: i32 = 2;
+// the exit is placed somewhere in the chunks this close to the start
+const EXIT_CHUNK_RADIUS: i32 = 2;
+// a cell in the middle of chunk (0, 0)
+const MAZE_START: mq::Vec2 = mq::vec2((CHUNK_SIZE / 2 + 1) as f32, (CHUNK_SIZE / 2 + 1) as f32);
 const MAZE_TILE_SIZE: f32 = 40.;
+// hex and polar mazes are one fixed size, corridors about as wide as a tile
+const HEX_COLS: i32 = 15;
+const HEX_ROWS: i32 = 17;
+const HEX_RADIUS: f32 = MAZE_TILE_SIZE * 0.6;
+const POLAR_RINGS: usize = 10;
+const WALL_THICKNESS: f32 = 2.;
+// shaped maze walls are checked against light this many px apart
+const SHADOW_CELL: f32 = 4.;
+// a shifted wall moves at most this many tiles from the passage it closed
+const SHIFT_REACH: i32 = 4;
+// seconds to fade out and back in when taking the stairs
+const STAIRS_FADE: f64 = 0.6;
 
 const PLAYER_W: f32 = 8.;
 const PLAYER_H: f32 = 10.;
@@ -30,14 +58,6 @@ const PLAYER_START: mq::Vec2 = mq::vec2(
     (PX_HEIGHT as f32 - PLAYER_H) / 2.,
 );
 
-const MAZE_PT: mq::Vec2 = mq::vec2(
-    -MAZE_SIZE * MAZE_TILE_SIZE / 2. + PLAYER_START.x,
-    -MAZE_SIZE * MAZE_TILE_SIZE / 2. + PLAYER_START.y,
-);
-
-const DITHER: [i32; 16] = [0, 8, 2, 10, 12, 4, 14, 6, 3, 11, 1, 9, 15, 7, 13, 5];
-const DITHER_SIZE: u32 = 4;
-
 // const COLOR_WHITE: mq::Color = mq::Color::new(236. / 255., 239. / 255., 244. / 255., 1.);
 // const COLOR_BLACK: mq::Color = mq::Color::new(40. / 255., 42. / 255., 54. / 255., 1.);
 // const COLOR_GREY: mq::Color = mq::Color::new(68. / 255., 71. / 255., 90. / 255., 1.);
@@ -46,6 +66,13 @@ const COLOR_WHITE: mq::Color = mq::Color::new(1., 1., 1., 1.);
 const COLOR_GREY: mq::Color = mq::Color::new(0.1, 0.1, 0.1, 1.);
 const COLOR_BLACK: mq::Color = mq::Color::new(0., 0., 0., 1.);
 const COLOR_GOLD: mq::Color = mq::Color::new(235. / 255., 203. / 255., 139. / 255., 1.);
+// a key is drawn the same color as the door it opens
+const LOCK_COLORS: [mq::Color; 4] = [
+    mq::Color::new(191. / 255., 97. / 255., 106. / 255., 1.),
+    mq::Color::new(129. / 255., 161. / 255., 193. / 255., 1.),
+    mq::Color::new(163. / 255., 190. / 255., 140. / 255., 1.),
+    mq::Color::new(180. / 255., 142. / 255., 173. / 255., 1.),
+];
 
 fn window_conf() -> mq::Conf {
     mq::Conf {
@@ -61,19 +88,516 @@ fn px_to_screen(x: f32, ratio: f32, offset: f32) -> f32 {
     x * ratio + offset
 }
 
-fn dither_idx(x: u32, y: u32) -> usize {
-    ((y % DITHER_SIZE) * DITHER_SIZE + (x % DITHER_SIZE)) as usize
+fn arg_value(name: &str) -> Option<String> {
+    let args: Vec<String> = std::env::args().collect();
+    args.iter()
+        .position(|arg| arg == name)
+        .map(|i| args.get(i + 1).cloned().unwrap_or_default())
+}
+
+fn has_arg(name: &str) -> bool {
+    std::env::args().any(|arg| arg == name)
+}
+
+#[cfg(target_arch = "wasm32")]
+extern "C" {
+    // from the plugin in index.html, copies the page's ?seed= into buf
+    // return: its length, -1 when there isn't one or it isn't a u64, which the plugin logs
+    fn nyx_seed_param(buf: *mut u8, max_len: i32) -> i32;
+}
+
+#[cfg(target_arch = "wasm32")]
+fn seed_param() -> Option<String> {
+    // the web build has no args, so the seed comes from the url instead
+    let mut buf = [0u8; 64];
+    let len = unsafe { nyx_seed_param(buf.as_mut_ptr(), buf.len() as i32) };
+    let len = usize::try_from(len).ok()?;
+    Some(String::from_utf8_lossy(&buf[..len]).into_owned())
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn seed_param() -> Option<String> {
+    arg_value("--seed")
+}
+
+fn parse_seed() -> u64 {
+    // usage: nyx --seed <u64>, or index.html?seed=<u64> on the web
+    let seed = match seed_param() {
+        Some(seed) => seed.parse().ok(),
+        None => return instant::now() as u64,
+    };
+    seed.unwrap_or_else(|| {
+        let err = "the seed should be an unsigned integer, like --seed 1234 or ?seed=1234";
+        if cfg!(target_arch = "wasm32") {
+            // nothing to exit to on a web page, so say so in the console and play a random maze
+            mq::error!("{}", err);
+            instant::now() as u64
+        } else {
+            eprintln!("{}", err);
+            std::process::exit(2);
+        }
+    })
+}
+
+fn parse_generator() -> Box<dyn MazeGenerator> {
+    // usage: nyx --algorithm <name>, see maze_generators::parse_generator
+    match arg_value("--algorithm") {
+        Some(name) => maze_generators::parse_generator(&name)
+            .unwrap_or_else(|| panic!("unknown --algorithm '{}'", name)),
+        None => Box::new(RecursiveBacktracker),
+    }
+}
+
+fn parse_braid() -> BraidConfig {
+    // usage: nyx --braid <dead end chance> --loops <loop chance>, both 0 to 1
+    let chance = |name: &str| match arg_value(name) {
+        Some(chance) => chance
+            .parse()
+            .unwrap_or_else(|_| panic!("{} expects a number from 0 to 1", name)),
+        None => 0.,
+    };
+    BraidConfig::new(chance("--braid"), chance("--loops"))
+}
+
+fn parse_rooms() -> RoomConfig {
+    // usage: nyx --rooms <attempts per chunk> --room-order <before|after>
+    let attempts = match arg_value("--rooms") {
+        Some(attempts) => attempts
+            .parse()
+            .expect("--rooms expects an unsigned integer"),
+        None => 0,
+    };
+    let order = match arg_value("--room-order").as_deref() {
+        Some("before") => RoomOrder::BeforeMaze,
+        Some("after") | None => RoomOrder::AfterMaze,
+        Some(order) => panic!("unknown --room-order '{}'", order),
+    };
+    RoomConfig::new(attempts, order)
+}
+
+fn parse_decor() -> DecorConfig {
+    // usage: nyx --decor <wall>,<floor>,<room>, the chance of each tile type, 0 to 1
+    match arg_value("--decor") {
+        Some(decor) => DecorConfig::parse(&decor).unwrap_or_else(|| {
+            panic!(
+                "--decor expects three numbers like 0.1,0.2,0.3, got '{}'",
+                decor
+            )
+        }),
+        None => DecorConfig::default(),
+    }
 }
 
-#[macroquad::main(window_conf)]
-async fn main() {
+fn parse_exit() -> ExitPlacement {
+    // usage: nyx --exit <farthest|edge|x,y>
+    match arg_value("--exit") {
+        Some(exit) => ExitPlacement::parse(&exit)
+            .unwrap_or_else(|| panic!("--exit expects farthest, edge or x,y, got '{}'", exit)),
+        None => ExitPlacement::default(),
+    }
+}
+
+fn parse_tonemap() -> Tonemap {
+    // usage: nyx --tonemap <clamp|reinhard>
+    match arg_value("--tonemap") {
+        Some(tonemap) => Tonemap::parse(&tonemap)
+            .unwrap_or_else(|| panic!("--tonemap expects clamp or reinhard, got '{}'", tonemap)),
+        None => Tonemap::Clamp,
+    }
+}
+
+fn parse_floors() -> usize {
+    // usage: nyx --floors <count>
+    match arg_value("--floors") {
+        Some(floors) => floors
+            .parse()
+            .ok()
+            .filter(|floors| *floors > 0)
+            .expect("--floors expects a positive integer"),
+        None => 1,
+    }
+}
+
+fn parse_doors() -> usize {
+    // usage: nyx --doors <count>, locked doors on the way to the exit
+    match arg_value("--doors") {
+        Some(doors) => doors.parse().expect("--doors expects an unsigned integer"),
+        None => 0,
+    }
+}
+
+fn parse_shift() -> Option<f64> {
+    // usage: nyx --shift <seconds between wall shifts>
+    arg_value("--shift").map(|interval| {
+        interval
+            .parse()
+            .ok()
+            .filter(|interval| *interval > 0.)
+            .expect("--shift expects a positive number of seconds")
+    })
+}
+
+fn parse_topology() -> Option<Box<dyn Shape>> {
+    // usage: nyx --topology <square|hex|polar>, square is the endless chunked maze
+    match arg_value("--topology").as_deref() {
+        Some("square") | None => None,
+        Some("hex") => Some(Box::new(HexShape::new(HEX_COLS, HEX_ROWS, HEX_RADIUS))),
+        Some("polar") => Some(Box::new(PolarShape::new(POLAR_RINGS, MAZE_TILE_SIZE))),
+        Some(topology) => panic!("unknown --topology '{}'", topology),
+    }
+}
+
+enum LevelMaze {
+    Tiles {
+        floors: MazeFloors,
+        start_tile: mq::IVec2,
+        exit_tile: mq::IVec2, // on the bottom floor
+    },
+    Shaped {
+        maze: ShapedMaze,
+        walls: Vec<Segment>,
+        exit_cell: usize,
+    },
+}
+
+struct Level {
+    seed: u64,
+    maze: LevelMaze,
+    // where world tile (0, 0), or the middle of a shaped maze, is drawn so the player starts
+    // on the start tile
+    maze_pt: mq::Vec2,
+    exit_distance: f32, // shortest walk from the start, in tiles
+    start_time: f64,
+    walked: f32, // in tiles
+    escaped_time: Option<f64>,
+    locks: Vec<Lock>, // on the top floor of a square maze
+    shift_rng: mq::rand::RandGenerator,
+    shift_time: f64, // when walls last shifted
+    floor: usize,
+    stairs: Option<(f64, usize)>, // (when the fade started, floor it ends on)
+    on_stairs: bool,              // the player has to step off before the stairs work again
+}
+impl Level {
+    fn name(&self) -> String {
+        match &self.maze {
+            LevelMaze::Tiles { floors, .. } => floors.floor(0).source.name(),
+            LevelMaze::Shaped { maze, .. } => format!("{} backtracker", maze.shape.name()),
+        }
+    }
+    fn exit_pt(&self) -> Option<mq::Vec2> {
+        // the middle of the exit in world px, if it's on this floor
+        match &self.maze {
+            LevelMaze::Tiles {
+                floors, exit_tile, ..
+            } => (self.floor == floors.len() - 1)
+                .then(|| self.maze_pt + (exit_tile.as_vec2() + 0.5) * MAZE_TILE_SIZE),
+            LevelMaze::Shaped {
+                maze, exit_cell, ..
+            } => Some(self.maze_pt + maze.shape.center(*exit_cell)),
+        }
+    }
+    fn at_exit(&self, pt: mq::Vec2) -> bool {
+        match &self.maze {
+            LevelMaze::Tiles {
+                floors, exit_tile, ..
+            } => {
+                self.floor == floors.len() - 1
+                    && ((pt - self.maze_pt) / MAZE_TILE_SIZE).floor().as_ivec2() == *exit_tile
+            }
+            LevelMaze::Shaped {
+                maze, exit_cell, ..
+            } => maze.shape.cell_at(pt - self.maze_pt) == Some(*exit_cell),
+        }
+    }
+}
+
+fn new_shaped_level(seed: u64, shape: Box<dyn Shape>) -> Level {
+    if parse_floors() > 1 {
+        panic!("--floors only works with the square topology");
+    }
+    if parse_doors() > 0 {
+        panic!("--doors only works with the square topology");
+    }
+    if parse_shift().is_some() {
+        panic!("--shift only works with the square topology");
+    }
+    if arg_value("--algorithm").is_some() {
+        panic!("--algorithm only works with the square topology, hex and polar use backtracker");
+    }
+    let start = shape.start();
+    let maze_pt = PLAYER_START - shape.center(start);
+    let rng = maze::create_rng(seed);
+    let mut maze = ShapedMaze::new(shape);
+    maze_generators::backtrack(&mut maze, start, &rng);
+
+    let distances = maze.distances(start);
+    let exit_cell = match parse_exit() {
+        ExitPlacement::Farthest => (0..distances.len())
+            .max_by_key(|cell| (distances[*cell], std::cmp::Reverse(*cell)))
+            .unwrap(),
+        ExitPlacement::RandomEdge => {
+            let edge = maze.edge_cells();
+            edge[rng.gen_range(0, edge.len())]
+        }
+        ExitPlacement::Fixed(_) => panic!("--exit x,y only works on the square topology"),
+    };
+
+    Level {
+        seed,
+        // px between cell centers, so it's in the same tiles as walked
+        exit_distance: maze.route_length(start, exit_cell).unwrap() / MAZE_TILE_SIZE,
+        maze_pt,
+        maze: LevelMaze::Shaped {
+            walls: maze.walls(),
+            maze,
+            exit_cell,
+        },
+        start_time: mq::get_time(),
+        walked: 0.,
+        escaped_time: None,
+        locks: vec![],
+        shift_rng: maze::create_rng(seed),
+        shift_time: mq::get_time(),
+        floor: 0,
+        stairs: None,
+        on_stairs: false,
+    }
+}
+
+fn masked_grid(seed: u64, mask: Option<&MazeMask>) -> Option<MazeGrid> {
+    // a new maze in the shape of the mask for every level
+    mask.map(|mask| maze_mask::create_masked_maze(mask, seed, parse_generator().as_ref()).0)
+}
+
+fn new_floors(seed: u64, fixed: Option<&MazeGrid>) -> (MazeFloors, mq::IVec2, mq::IVec2, u32) {
+    // return: (floors, start tile, exit tile, exit distance), doesn't need a window
+    let floor_count = parse_floors();
+    let (mut floors, start_tile, exit_radius) = match fixed {
+        Some(grid) => {
+            if floor_count > 1 {
+                panic!("--maze and --mask mazes are one floor, they can't be used with --floors");
+            }
+            // load the whole hand authored maze so the exit can go anywhere in it
+            let chunks = (grid.width().max(grid.height()) as i32 + CHUNK_SIZE - 1) / CHUNK_SIZE;
+            (
+                MazeFloors::new(vec![ChunkedMaze::from_grid(grid.clone(), CHUNK_SIZE)]),
+                maze_io::find_start(grid).unwrap(),
+                chunks.max(EXIT_CHUNK_RADIUS),
+            )
+        }
+        None => (
+            MazeFloors::new(
+                (0..floor_count)
+                    .map(|floor| {
+                        ChunkedMaze::new(
+                            maze_floors::floor_seed(seed, floor),
+                            CHUNK_SIZE,
+                            parse_generator(),
+                            parse_braid(),
+                            parse_rooms(),
+                        )
+                    })
+                    .collect(),
+            ),
+            MAZE_START.as_ivec2(),
+            EXIT_CHUNK_RADIUS,
+        ),
+    };
+    if parse_doors() > 0 {
+        // a door only has to be a cut in what's loaded now, so that's all the maze there is
+        let maze_map = &mut floors.floors[0];
+        let (center, _) = maze_map.to_chunk(start_tile);
+        let radius = mq::IVec2::splat(exit_radius);
+        maze_map.bounds = Some((center - radius, center + radius));
+    }
+    floors.update(start_tile, exit_radius, exit_radius);
+
+    // the exit is on the bottom floor, the way there goes down every staircase in between
+    let placement = parse_exit();
+    let bottom = floors.len() - 1;
+    let exit_tile = maze_exit::place_exit(
+        floors.floor(bottom),
+        start_tile,
+        placement,
+        &maze::create_rng(seed),
+    )
+    .unwrap_or_else(|| panic!("{:?} exit can't be reached from the start", placement));
+    let exit_distance = floors
+        .distance((0, start_tile), (bottom, exit_tile))
+        .unwrap_or_else(|| panic!("no stairs down to the exit near the start"));
+    (floors, start_tile, exit_tile, exit_distance)
+}
+
+fn new_level(seed: u64, fixed: Option<&MazeGrid>) -> Level {
+    if let Some(shape) = parse_topology() {
+        if fixed.is_some() {
+            panic!("--maze and --mask mazes are square, they can't be used with --topology");
+        }
+        return new_shaped_level(seed, shape);
+    }
+
+    let (floors, start_tile, exit_tile, mut exit_distance) = new_floors(seed, fixed);
+    let mut locks = vec![];
+    let door_count = parse_doors();
+    if door_count > 0 {
+        if floors.len() > 1 {
+            panic!("--doors only works with one floor");
+        }
+        locks = maze_doors::place_locks(
+            floors.floor(0),
+            start_tile,
+            exit_tile,
+            door_count,
+            &maze::create_rng(seed),
+        );
+        if locks.len() < door_count {
+            mq::warn!(
+                "only room for {} of {} locked doors on the way to the exit",
+                locks.len(),
+                door_count
+            );
+        }
+        exit_distance =
+            maze_doors::route_length(floors.floor(0), start_tile, exit_tile, &locks).unwrap();
+    }
+    Level {
+        seed,
+        maze: LevelMaze::Tiles {
+            floors,
+            start_tile,
+            exit_tile,
+        },
+        maze_pt: -(start_tile.as_vec2() + 0.5) * MAZE_TILE_SIZE + PLAYER_START,
+        exit_distance: exit_distance as f32,
+        start_time: mq::get_time(),
+        walked: 0.,
+        escaped_time: None,
+        locks,
+        shift_rng: maze::create_rng(seed),
+        shift_time: mq::get_time(),
+        floor: 0,
+        stairs: None,
+        on_stairs: false,
+    }
+}
+
+fn export_level(level: &Level, path: &str) {
+    // usage: nyx --export <maze.txt|maze.png>, saves every chunk loaded around the start
+    let (maze_map, start_tile) = match &level.maze {
+        LevelMaze::Tiles {
+            floors, start_tile, ..
+        } => (floors.floor(level.floor), *start_tile),
+        LevelMaze::Shaped { .. } => panic!("--export only works on the square topology"),
+    };
+    let (grid, min) = loaded_grid(maze_map);
+    match maze_io::save_maze(&grid, path) {
+        Ok(()) => println!("saved maze to {}, start at {}", path, start_tile - min),
+        Err(err) => panic!("can't save maze to '{}': {}", path, err),
+    }
+}
+
+fn loaded_grid(maze_map: &ChunkedMaze) -> (MazeGrid, mq::IVec2) {
+    // return: (every loaded chunk as one grid, world tile of its top left)
+    let (min, max) = maze_map.chunks().fold(
+        (mq::IVec2::splat(i32::MAX), mq::IVec2::splat(i32::MIN)),
+        |(min, max), (chunk, _)| (min.min(*chunk), max.max(*chunk)),
+    );
+    let size = (max - min + 1) * CHUNK_SIZE;
+    (
+        maze_map.window(min * CHUNK_SIZE, size.x as u32, size.y as u32),
+        min * CHUNK_SIZE,
+    )
+}
+
+fn print_metrics() {
+    // usage: nyx --metrics [--seeds <count>], measures the chunks loaded around the start of
+    // count levels from --seed on, the same ones the game would play
+    if parse_topology().is_some() || parse_floors() > 1 {
+        panic!("--metrics only measures one floor of the square topology");
+    }
+    let fixed = arg_value("--maze").map(|path| {
+        std::fs::read(&path)
+            .map_err(|err| maze_io::MazeFileError::Io(err.to_string()))
+            .and_then(|bytes| maze_io::from_bytes(&path, &bytes))
+            .unwrap_or_else(|err| panic!("can't load maze '{}': {}", path, err))
+    });
+    let mask = arg_value("--mask").map(|path| {
+        if fixed.is_some() {
+            panic!("--mask makes its own maze, it can't be used with --maze");
+        }
+        std::fs::read(&path)
+            .map_err(|err| maze_io::MazeFileError::Io(err.to_string()))
+            .and_then(|bytes| MazeMask::from_png(&bytes))
+            .unwrap_or_else(|err| panic!("can't load mask '{}': {}", path, err))
+    });
+    let count: u64 = match arg_value("--seeds") {
+        Some(count) => count.parse().expect("--seeds expects an unsigned integer"),
+        None => 1,
+    };
+
+    let seed = parse_seed();
+    let mut name = String::new();
+    let metrics = (0..count)
+        .map(|i| {
+            let seed = seed.wrapping_add(i);
+            let masked = masked_grid(seed, mask.as_ref());
+            let (floors, start_tile, exit_tile, _) =
+                new_floors(seed, masked.as_ref().or(fixed.as_ref()));
+            let (grid, min) = loaded_grid(floors.floor(0));
+            name = floors.floor(0).source.name();
+            let metrics = MazeMetrics::new(&grid, start_tile - min, exit_tile - min).unwrap();
+            (seed, metrics)
+        })
+        .collect::<Vec<(u64, MazeMetrics)>>();
+
+    println!("{}", name);
+    if let [(seed, metrics)] = metrics.as_slice() {
+        println!("seed:             {}", seed);
+        println!("{}", metrics);
+    } else {
+        println!("{:>20} {}", "seed", MazeMetrics::header());
+        for (seed, metrics) in &metrics {
+            println!("{:>20} {}", seed, metrics.row());
+        }
+        let metrics = metrics.iter().map(|(_, m)| *m).collect::<Vec<_>>();
+        println!("{:>20} {}", "average", MazeMetrics::average(&metrics).row());
+    }
+}
+
+fn new_player() -> Player {
+    let mut player = Player::new(
+        PLAYER_START - mq::vec2(PLAYER_W, PLAYER_H) / 2.,
+        PLAYER_W,
+        PLAYER_H,
+        Light::new(
+            mq::Vec2::ZERO,
+            3.6,
+            LightMode::Sin(0.15, 4., 0.),
+            COLOR_GREY,
+            Falloff::default(),
+        ),
+    );
+    player.update_light_pt();
+    player
+}
+
+fn main() {
+    // metrics are printed without opening a window
+    if has_arg("--metrics") {
+        print_metrics();
+    } else {
+        macroquad::Window::from_config(window_conf(), run());
+    }
+}
+
+async fn run() {
     // ---------------------------------------------------------------------- //
 
-    mq::rand::srand(instant::now() as u64);
+    let seed = parse_seed();
 
     let mut camera =
         mq::Camera2D::from_display_rect(mq::Rect::new(0.0, 0.0, PX_WIDTH as f32, PX_HEIGHT as f32));
-    camera.render_target = Some(mq::render_target(PX_WIDTH as u32, PX_HEIGHT as u32));
+    camera.render_target = Some(mq::render_target(PX_WIDTH, PX_HEIGHT));
     camera
         .render_target
         .unwrap()
@@ -88,37 +612,69 @@ async fn main() {
     // ---------------------------------------------------------------------- //
 
     // ---------------------------------------------------------------------- //
-    let maze_map = maze::create_maze_map(MAZE_SIZE, MAZE_START, COLOR_WHITE, COLOR_BLACK);
-    let maze_image = maze::create_maze_texture(
-        &maze_map,
-        MAZE_SIZE,
-        MAZE_TILE_SIZE,
-        COLOR_WHITE,
-        COLOR_BLACK,
+    // usage: nyx --maze <maze.txt|maze.png> plays a hand authored maze instead
+    let fixed = match arg_value("--maze") {
+        Some(path) => Some(
+            maze_io::load_maze(&path)
+                .await
+                .unwrap_or_else(|err| panic!("can't load maze '{}': {}", path, err)),
+        ),
+        None => None,
+    };
+    // usage: nyx --mask <mask.png> fits each maze to the dark pixels, one pixel per cell
+    let mask = match arg_value("--mask") {
+        Some(path) => {
+            if fixed.is_some() {
+                panic!("--mask makes its own maze, it can't be used with --maze");
+            }
+            let bytes = mq::load_file(&path)
+                .await
+                .unwrap_or_else(|err| panic!("can't load mask '{}': {}", path, err));
+            Some(
+                MazeMask::from_png(&bytes)
+                    .unwrap_or_else(|err| panic!("can't load mask '{}': {}", path, err)),
+            )
+        }
+        None => None,
+    };
+    let new_masked_level = |seed: u64| {
+        let masked = masked_grid(seed, mask.as_ref());
+        new_level(seed, masked.as_ref().or(fixed.as_ref()))
+    };
+    let mut level = new_masked_level(seed);
+    if let Some(path) = arg_value("--export") {
+        export_level(&level, &path);
+    }
+    let atlas = TileAtlas::new(
+        mq::load_texture("assets/maze_atlas.png").await.unwrap(),
+        AtlasDef::parse(&mq::load_string("assets/maze_atlas.txt").await.unwrap())
+            .unwrap_or_else(|err| panic!("bad assets/maze_atlas.txt: {}", err)),
     );
-    let maze_texture = mq::Texture2D::from_image(&maze_image);
-    maze_texture.set_filter(mq::FilterMode::Nearest);
     // ---------------------------------------------------------------------- //
 
-    let mut player = Player::new(
-        PLAYER_START - mq::vec2(PLAYER_W, PLAYER_H) / 2.,
-        PLAYER_W,
-        PLAYER_H,
-        Light::new(
-            mq::Vec2::ZERO,
-            3.6,
-            LightMode::Sin(0.15, 4., 0.),
-            COLOR_GREY,
-        ),
-    );
-    player.update_light_pt();
+    let mut lighting = Lighting::new(parse_tonemap())
+        .unwrap_or_else(|err| panic!("can't build the lighting shader: {}", err));
+    let decor = parse_decor();
+    let shift = parse_shift();
+    let mut player = new_player();
+    let mut dropped_lights = 0; // warned about once each time more go missing
 
-    let /*mut*/ lights: Vec<Light> = vec![Light::new(
-        PLAYER_START,
-        1.2,
-        LightMode::Sin(0.05, 5., 0.),
-        COLOR_GREY,
-    )];
+    // each floor only lights itself
+    let /*mut*/ floor_lights: Vec<Vec<Light>> = (0..parse_floors())
+        .map(|floor| {
+            if floor == 0 {
+                vec![Light::new(
+                    PLAYER_START,
+                    1.2,
+                    LightMode::Sin(0.05, 5., 0.),
+                    COLOR_GREY,
+                    Falloff::default(),
+                )]
+            } else {
+                vec![]
+            }
+        })
+        .collect();
 
     // let mut objects: Vec<mq::Rect> = vec![
     //     mq::Rect::new(0., 0., 20., 30.),
@@ -138,57 +694,260 @@ async fn main() {
         // ------------------------------------------------------------------ //
 
         // ------------------------------------------------------------------ //
-        player.update(&mut cm, delta); // moves player
+        if level.escaped_time.is_some() && mq::is_key_pressed(mq::KeyCode::Enter) {
+            // next level
+            level = new_masked_level(level.seed.wrapping_add(1));
+            player = new_player();
+            cm = CameraManager::new(mq::Vec2::ZERO, -PLAYER_START);
+        }
+
+        if let Some((stairs_time, new_floor)) = level.stairs {
+            // swap floors while the screen is dark
+            let t = mq::get_time() - stairs_time;
+            if t >= STAIRS_FADE / 2. {
+                level.floor = new_floor;
+            }
+            if t >= STAIRS_FADE {
+                level.stairs = None;
+            }
+        }
+        let lights = &floor_lights[level.floor];
+
+        let last_player_pt = player.pt;
+        if level.escaped_time.is_none() && level.stairs.is_none() {
+            player.update(&mut cm, delta); // moves player
+        }
+
+        let player_center = player.pt + mq::vec2(player.w, player.h) / 2.;
+        if let LevelMaze::Tiles {
+            floors, exit_tile, ..
+        } = &mut level.maze
+        {
+            let player_tile = ((player_center - level.maze_pt) / MAZE_TILE_SIZE)
+                .floor()
+                .as_ivec2();
+            floors.update(player_tile, CHUNK_LOAD_RADIUS, CHUNK_UNLOAD_RADIUS);
+
+            let stairs = floors.stairs_at(level.floor, player_tile);
+            if let Some(new_floor) = stairs {
+                if !level.on_stairs && level.stairs.is_none() {
+                    level.stairs = Some((mq::get_time(), new_floor));
+                }
+            }
+            level.on_stairs = stairs.is_some();
+
+            for (i, lock) in level.locks.iter_mut().enumerate() {
+                if !lock.key_taken && lock.key == player_tile {
+                    lock.key_taken = true;
+                    player.held_keys.push(i);
+                }
+            }
+
+            let shifting = level.escaped_time.is_none() && level.stairs.is_none();
+            if let Some(interval) = shift.filter(|_| shifting) {
+                if mq::get_time() - level.shift_time >= interval {
+                    level.shift_time = mq::get_time();
+                    // only where the player can't see, as far as the dithered light reaches
+                    let light_radius = player.light.reach() / MAZE_TILE_SIZE + 1.;
+                    let (doors, mut protected) = if level.floor == 0 {
+                        (
+                            level
+                                .locks
+                                .iter()
+                                .filter(|lock| !lock.open)
+                                .map(|lock| lock.door)
+                                .collect(),
+                            level
+                                .locks
+                                .iter()
+                                .filter(|lock| !lock.key_taken)
+                                .map(|lock| lock.key)
+                                .collect(),
+                        )
+                    } else {
+                        (vec![], vec![])
+                    };
+                    if level.floor == floors.len() - 1 {
+                        protected.push(*exit_tile);
+                    }
+                    maze_shift::shift_wall(
+                        &mut floors.floors[level.floor],
+                        (player_tile, light_radius),
+                        SHIFT_REACH,
+                        &doors,
+                        &protected,
+                        &level.shift_rng,
+                    );
+                }
+            }
+        }
 
-        let world_d = player.pt - MAZE_PT;
+        let world_d = player.pt - level.maze_pt;
         let map_d = world_d / MAZE_TILE_SIZE;
         let map_box_pt = mq::vec2(map_d.x.floor(), map_d.y.floor()) - mq::Vec2::ONE;
-        let world_box_pt = map_box_pt * MAZE_TILE_SIZE + MAZE_PT;
+        let world_box_pt = map_box_pt * MAZE_TILE_SIZE + level.maze_pt;
 
         let mut wall_collide = false;
         let mut cs: Vec<mq::Rect> = vec![];
         // todo: clean; flip Y??; after wall collide try next key
-        for y in 0..3 {
-            for x in 0..3 {
-                let map_pt = map_box_pt + mq::vec2(x as f32, y as f32);
-                if map_pt.x < 0.
-                    || map_pt.y < 0.
-                    || map_pt.x >= MAZE_SIZE as f32
-                    || map_pt.y >= MAZE_SIZE as f32
-                {
-                    continue;
+        match &level.maze {
+            LevelMaze::Tiles { floors, .. } => {
+                let maze_map = floors.floor(level.floor);
+                for y in 0..3 {
+                    for x in 0..3 {
+                        let map_pt = map_box_pt + mq::vec2(x as f32, y as f32);
+                        // closed doors are walls until the player walks into one with its key
+                        let door = level
+                            .locks
+                            .iter()
+                            .position(|lock| !lock.open && lock.door == map_pt.as_ivec2());
+                        if maze_map.is_wall(map_pt.as_ivec2()) || door.is_some() {
+                            let world_pt =
+                                world_box_pt + mq::vec2(x as f32, y as f32) * MAZE_TILE_SIZE;
+                            let tile_rect = mq::Rect::new(
+                                world_pt.x,
+                                world_pt.y,
+                                MAZE_TILE_SIZE,
+                                MAZE_TILE_SIZE,
+                            );
+                            let wc = player.collide_immovable(&mut cm, tile_rect);
+                            wall_collide = wc || wall_collide;
+                            if wc {
+                                cs.push(tile_rect);
+                            }
+                            if let Some(door) = door.filter(|_| wc) {
+                                if let Some(key) =
+                                    player.held_keys.iter().position(|key| *key == door)
+                                {
+                                    player.held_keys.remove(key);
+                                    level.locks[door].open = true;
+                                }
+                            }
+                        }
+                    }
                 }
-                let map_px = maze_map.get_pixel(map_pt.x as u32, map_pt.y as u32);
-                if map_px == COLOR_WHITE {
-                    let world_pt = world_box_pt + mq::vec2(x as f32, y as f32) * MAZE_TILE_SIZE;
-                    let tile_rect =
-                        mq::Rect::new(world_pt.x, world_pt.y, MAZE_TILE_SIZE, MAZE_TILE_SIZE);
-                    let wc = player.collide_immovable(&mut cm, tile_rect);
+            }
+            LevelMaze::Shaped { walls, .. } => {
+                for (a, b) in walls.iter() {
+                    let wc = player.collide_segment(
+                        &mut cm,
+                        level.maze_pt + *a,
+                        level.maze_pt + *b,
+                        WALL_THICKNESS,
+                    );
                     wall_collide = wc || wall_collide;
-                    if wc {
-                        cs.push(tile_rect);
-                    }
                 }
             }
         }
+
+        level.walked += (player.pt - last_player_pt).length() / MAZE_TILE_SIZE;
+        if level.escaped_time.is_none() && level.at_exit(player_center) {
+            level.escaped_time = Some(mq::get_time());
+        }
         // ------------------------------------------------------------------ //
 
         // ------------------------------------------------------------------ //
         mq::set_camera(&camera);
         mq::clear_background(COLOR_BLACK);
 
-        let maze_pt = cm.calc_offset(MAZE_PT);
-        mq::draw_texture_ex(
-            maze_texture,
-            maze_pt.x,
-            maze_pt.y,
-            mq::WHITE,
-            mq::DrawTextureParams {
-                dest_size: Some(mq::Vec2::splat(MAZE_SIZE * MAZE_TILE_SIZE)),
-                flip_y: false,
-                ..Default::default()
-            },
-        );
+        match &level.maze {
+            LevelMaze::Tiles { floors, .. } => {
+                maze_atlas::draw_maze(
+                    floors.floor(level.floor),
+                    &atlas,
+                    &cm,
+                    level.maze_pt,
+                    mq::vec2(PX_WIDTH as f32, PX_HEIGHT as f32),
+                    MAZE_TILE_SIZE,
+                );
+                maze_decor::draw_decorations(
+                    floors.floor(level.floor),
+                    &decor,
+                    &cm,
+                    level.maze_pt,
+                    mq::vec2(PX_WIDTH as f32, PX_HEIGHT as f32),
+                    MAZE_TILE_SIZE,
+                );
+                for (tile, new_floor) in floors.stairs(level.floor) {
+                    // three steps, narrowing on the way down
+                    let stairs_pt = cm.calc_offset(level.maze_pt + tile.as_vec2() * MAZE_TILE_SIZE);
+                    for step in 0..3 {
+                        let w = if new_floor > level.floor {
+                            MAZE_TILE_SIZE * (0.7 - 0.15 * step as f32)
+                        } else {
+                            MAZE_TILE_SIZE * (0.4 + 0.15 * step as f32)
+                        };
+                        mq::draw_rectangle(
+                            stairs_pt.x + (MAZE_TILE_SIZE - w) / 2.,
+                            stairs_pt.y + MAZE_TILE_SIZE * (0.2 + 0.22 * step as f32),
+                            w,
+                            MAZE_TILE_SIZE * 0.14,
+                            COLOR_WHITE,
+                        );
+                    }
+                }
+            }
+            LevelMaze::Shaped { walls, .. } => {
+                for (a, b) in walls.iter() {
+                    let (a, b) = (
+                        cm.calc_offset(level.maze_pt + *a),
+                        cm.calc_offset(level.maze_pt + *b),
+                    );
+                    mq::draw_line(a.x, a.y, b.x, b.y, WALL_THICKNESS, COLOR_WHITE);
+                }
+            }
+        }
+
+        if level.floor == 0 {
+            for (i, lock) in level.locks.iter().enumerate() {
+                let color = LOCK_COLORS[i % LOCK_COLORS.len()];
+                if !lock.open {
+                    // a slab with a keyhole
+                    let door_pt =
+                        cm.calc_offset(level.maze_pt + lock.door.as_vec2() * MAZE_TILE_SIZE);
+                    mq::draw_rectangle(door_pt.x, door_pt.y, MAZE_TILE_SIZE, MAZE_TILE_SIZE, color);
+                    mq::draw_circle(
+                        door_pt.x + MAZE_TILE_SIZE / 2.,
+                        door_pt.y + MAZE_TILE_SIZE * 0.45,
+                        MAZE_TILE_SIZE / 10.,
+                        COLOR_BLACK,
+                    );
+                    mq::draw_rectangle(
+                        door_pt.x + MAZE_TILE_SIZE * 0.47,
+                        door_pt.y + MAZE_TILE_SIZE * 0.45,
+                        MAZE_TILE_SIZE * 0.06,
+                        MAZE_TILE_SIZE * 0.2,
+                        COLOR_BLACK,
+                    );
+                }
+                if !lock.key_taken {
+                    // a ring and a shaft
+                    let key_pt =
+                        cm.calc_offset(level.maze_pt + (lock.key.as_vec2() + 0.5) * MAZE_TILE_SIZE);
+                    mq::draw_circle_lines(key_pt.x - 5., key_pt.y, 3., 1.5, color);
+                    mq::draw_line(key_pt.x - 2., key_pt.y, key_pt.x + 6., key_pt.y, 1.5, color);
+                    mq::draw_line(
+                        key_pt.x + 4.,
+                        key_pt.y,
+                        key_pt.x + 4.,
+                        key_pt.y + 3.,
+                        1.5,
+                        color,
+                    );
+                }
+            }
+        }
+
+        if let Some(exit_pt) = level.exit_pt() {
+            let exit_pt = cm.calc_offset(exit_pt);
+            mq::draw_rectangle(
+                exit_pt.x - MAZE_TILE_SIZE / 4.,
+                exit_pt.y - MAZE_TILE_SIZE / 4.,
+                MAZE_TILE_SIZE / 2.,
+                MAZE_TILE_SIZE / 2.,
+                COLOR_GOLD,
+            );
+        }
 
         let world_pt_cm = cm.calc_offset(world_box_pt);
         mq::draw_rectangle_lines(
@@ -221,6 +980,19 @@ async fn main() {
         }
 
         player.draw(COLOR_GOLD, &cm);
+
+        if let Some((stairs_time, _)) = level.stairs {
+            // dark at the halfway point, when the floor swaps
+            let t = ((mq::get_time() - stairs_time) / STAIRS_FADE) as f32;
+            let alpha = 1. - (t * 2. - 1.).abs();
+            mq::draw_rectangle(
+                0.,
+                0.,
+                PX_WIDTH as f32,
+                PX_HEIGHT as f32,
+                mq::Color::new(0., 0., 0., alpha.clamp(0., 1.)),
+            );
+        }
         // ------------------------------------------------------------------ //
 
         mq::set_camera(&mq::Camera2D::from_display_rect(mq::Rect::new(
@@ -232,15 +1004,11 @@ async fn main() {
         mq::clear_background(COLOR_GOLD);
 
         // ------------------------------------------------------------------ //
-        let image_in = camera.render_target.unwrap().texture.get_texture_data();
-        let mut image_out =
-            mq::Image::gen_image_color(PX_WIDTH as u16, PX_HEIGHT as u16, COLOR_BLACK);
-
         let mut draw_lights: Vec<Light> = lights
             .clone()
             .into_iter()
             .filter(|light| {
-                let p = light.power * 16.;
+                let p = light.reach();
                 let pt = cm.calc_offset(light.pt);
                 p + pt.x > 0.
                     && p + pt.y > 0.
@@ -248,57 +1016,61 @@ async fn main() {
                     && pt.y - p < PX_HEIGHT as f32
             })
             .collect();
-        draw_lights.push(player.light);
-
-        let light_powers = draw_lights
-            .iter()
-            .map(|light| light.calc_power(mq::get_time() as f32))
-            .collect::<Vec<f32>>();
-        let light_pts = draw_lights
-            .iter()
-            .map(|light| cm.calc_offset(light.pt))
-            .collect::<Vec<mq::Vec2>>();
-
-        for x in 0..PX_WIDTH {
-            for y in 0..PX_HEIGHT {
-                let src_y = PX_HEIGHT - y - 1;
-                for (i, light) in draw_lights.iter().enumerate() {
-                    let dx = light_pts[i].x as i32 - x as i32;
-                    let dy = light_pts[i].y as i32 - y as i32;
-                    let dist = ((dx * dx + dy * dy) as f32).sqrt();
-
-                    // if dist < light_powers[i] * 4.
-                    //     || dist / light_powers[i] <= DITHER[dither_idx(x, y)] as f32 {
-                    if true {
-                        let screen_px_color = image_in.get_pixel(x, src_y);
-                        image_out.set_pixel(
-                            x,
-                            y,
-                            if screen_px_color == COLOR_BLACK {
-                                light.color
-                            } else {
-                                screen_px_color
-                            },
-                        );
+        // the shader only has max_lights slots, the player's light and the nearest ones get them
+        let nearness = |light: &Light| light.pt.distance_squared(player.light.pt);
+        draw_lights.sort_by(|a, b| nearness(a).total_cmp(&nearness(b)));
+        let dropped = draw_lights.len().saturating_sub(lighting.max_lights() - 1);
+        if dropped > dropped_lights {
+            mq::warn!(
+                "{} lights on screen, the {} farthest from the player aren't drawn",
+                draw_lights.len() + 1,
+                dropped
+            );
+        }
+        dropped_lights = dropped;
+        draw_lights.truncate(lighting.max_lights() - 1);
+        draw_lights.push(player.light.clone());
+        for light in draw_lights.iter_mut() {
+            light.pt = cm.calc_offset(light.pt);
+            light.power = light.calc_power(mq::get_time() as f32);
+        }
 
-                        break;
-                    }
+        // closed doors block light like walls
+        let closed_doors = if level.floor == 0 {
+            level
+                .locks
+                .iter()
+                .filter(|lock| !lock.open)
+                .map(|lock| lock.door)
+                .collect()
+        } else {
+            vec![]
+        };
+        let locked;
+        let maze_pt = cm.calc_offset(level.maze_pt);
+        let occluder = match &level.maze {
+            LevelMaze::Tiles { floors, .. } => {
+                locked = Locked {
+                    maze: floors.floor(level.floor),
+                    doors: &closed_doors,
+                };
+                Occluder::Tiles {
+                    maze: &locked,
+                    maze_pt,
+                    tile_size: MAZE_TILE_SIZE,
                 }
             }
-        }
-        // overwrite the texture with the new image
-        camera.render_target.unwrap().texture.update(&image_out);
-        // ------------------------------------------------------------------ //
-
-        mq::draw_texture_ex(
-            camera.render_target.unwrap().texture,
-            left_offset,
-            top_offset,
-            mq::WHITE,
-            mq::DrawTextureParams {
-                dest_size: Some(mq::vec2(draw_width, draw_height)),
-                ..Default::default()
+            LevelMaze::Shaped { walls, .. } => Occluder::Walls {
+                walls,
+                maze_pt,
+                cell_size: SHADOW_CELL,
             },
+        };
+        lighting.draw(
+            camera.render_target.unwrap().texture,
+            &draw_lights,
+            Some(&occluder),
+            mq::Rect::new(left_offset, top_offset, draw_width, draw_height),
         );
 
         // ------------------------------------------------------------------ //
@@ -316,6 +1088,60 @@ async fn main() {
                 ..Default::default()
             },
         );
+        let mut seed_str = format!("{} SEED {}", level.name().to_uppercase(), level.seed);
+        if let LevelMaze::Tiles { floors, .. } = &level.maze {
+            if floors.len() > 1 {
+                seed_str = format!("FLOOR {}/{} {}", level.floor + 1, floors.len(), seed_str);
+            }
+        }
+        if !level.locks.is_empty() {
+            let opened = level.locks.iter().filter(|lock| lock.open).count();
+            seed_str = format!(
+                "KEYS {} DOORS {}/{} {}",
+                player.held_keys.len(),
+                opened,
+                level.locks.len(),
+                seed_str
+            );
+        }
+        let seed_size = mq::measure_text(&seed_str, Some(font), font_size, 1.);
+        mq::draw_text_ex(
+            &seed_str,
+            px_to_screen(PX_WIDTH as f32 - 1., ratio, left_offset) - seed_size.width,
+            px_to_screen(PX_HEIGHT as f32 - 1., ratio, top_offset),
+            mq::TextParams {
+                font,
+                color: COLOR_WHITE,
+                font_size,
+                ..Default::default()
+            },
+        );
+
+        if let Some(escaped_time) = level.escaped_time {
+            let summary = [
+                "ESCAPED".to_owned(),
+                format!("TIME {:.1}s", escaped_time - level.start_time),
+                format!(
+                    "WALKED {:.0} TILES, SHORTEST {:.0}",
+                    level.walked, level.exit_distance
+                ),
+                "ENTER TO GO DEEPER".to_owned(),
+            ];
+            for (i, line) in summary.iter().enumerate() {
+                let line_size = mq::measure_text(line, Some(font), font_size, 1.);
+                mq::draw_text_ex(
+                    line,
+                    px_to_screen(PX_WIDTH as f32 / 2., ratio, left_offset) - line_size.width / 2.,
+                    px_to_screen(PX_HEIGHT as f32 / 3. + i as f32 * 10., ratio, top_offset),
+                    mq::TextParams {
+                        font,
+                        color: COLOR_GOLD,
+                        font_size,
+                        ..Default::default()
+                    },
+                );
+            }
+        }
         // ------------------------------------------------------------------ //
 
         println!("{:?} {:?}", player.keys, player.wall_dirs);
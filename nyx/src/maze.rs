@@ -1,118 +1,521 @@
-use macroquad::prelude as mq;
+use crate::maze_generators::MazeGenerator;
 
-pub fn create_maze_map(
-    maze_size: f32,
-    maze_start: mq::Vec2,
-    color_white: mq::Color,
-    color_black: mq::Color,
-) -> mq::Image {
-    let neighbor_offsets = [
-        mq::Vec2::new(0., -2.),
-        mq::Vec2::new(2., 0.),
-        mq::Vec2::new(0., 2.),
-        mq::Vec2::new(-2., 0.),
-    ];
-    let mut stack: Vec<mq::Vec2> = vec![maze_start];
+use std::collections::VecDeque;
+
+pub const NEIGHBOR_OFFSETS: [glam::IVec2; 4] = [
+    glam::ivec2(0, -1),
+    glam::ivec2(1, 0),
+    glam::ivec2(0, 1),
+    glam::ivec2(-1, 0),
+];
 
-    let mut maze_image =
-        mq::Image::gen_image_color(maze_size as u16, maze_size as u16, color_white);
+#[derive(PartialEq, Eq, Debug, Copy, Clone)]
+pub enum Tile {
+    Wall,
+    Floor,
+    Room, // floor inside a chamber
+}
+impl Tile {
+    pub fn is_open(&self) -> bool {
+        *self != Tile::Wall
+    }
+}
 
-    let mut first = true;
+#[derive(PartialEq, Eq, Debug, Copy, Clone)]
+pub enum CarveStep {
+    Cell(glam::IVec2),
+    Passage(glam::IVec2, glam::IVec2), // between two neighboring cells
+}
 
-    while !stack.is_empty() {
-        let current_cell = stack.pop().unwrap();
-        let offset_locs = neighbor_offsets
+#[derive(Debug, Clone)]
+pub struct MazeGrid {
+    width: u32,
+    height: u32,
+    tiles: Vec<Tile>,
+    revision: u64, // bumped on every tile change so caches can tell they're stale
+    steps: Option<Vec<CarveStep>>, // carves that changed a tile, while recording
+}
+impl PartialEq for MazeGrid {
+    fn eq(&self, other: &MazeGrid) -> bool {
+        self.width == other.width && self.height == other.height && self.tiles == other.tiles
+    }
+}
+impl Eq for MazeGrid {}
+impl MazeGrid {
+    pub fn new(width: u32, height: u32, fill: Tile) -> MazeGrid {
+        MazeGrid {
+            width,
+            height,
+            tiles: vec![fill; (width * height) as usize],
+            revision: 0,
+            steps: None,
+        }
+    }
+    pub fn record_steps(&mut self) {
+        self.steps = Some(vec![]);
+    }
+    pub fn take_steps(&mut self) -> Vec<CarveStep> {
+        // return: every carve since record_steps, and stops recording
+        self.steps.take().unwrap_or_default()
+    }
+    fn push_step(&mut self, revision: u64, step: CarveStep) {
+        // only carves that opened something are worth replaying
+        if let Some(steps) = &mut self.steps {
+            if self.revision != revision {
+                steps.push(step);
+            }
+        }
+    }
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+    pub fn in_bounds(&self, pt: glam::IVec2) -> bool {
+        pt.x >= 0 && pt.y >= 0 && pt.x < self.width as i32 && pt.y < self.height as i32
+    }
+    fn idx(&self, pt: glam::IVec2) -> usize {
+        (pt.y as u32 * self.width + pt.x as u32) as usize
+    }
+    pub fn get(&self, pt: glam::IVec2) -> Option<Tile> {
+        if self.in_bounds(pt) {
+            Some(self.tiles[self.idx(pt)])
+        } else {
+            None
+        }
+    }
+    pub fn set(&mut self, pt: glam::IVec2, tile: Tile) -> bool {
+        // return: whether pt was in bounds
+        if self.in_bounds(pt) {
+            let idx = self.idx(pt);
+            if self.tiles[idx] != tile {
+                self.tiles[idx] = tile;
+                self.revision += 1;
+            }
+            true
+        } else {
+            false
+        }
+    }
+    pub fn is_wall(&self, pt: glam::IVec2) -> bool {
+        self.get(pt) == Some(Tile::Wall)
+    }
+    pub fn is_open(&self, pt: glam::IVec2) -> bool {
+        self.get(pt).is_some_and(|tile| tile.is_open())
+    }
+    pub fn neighbors(&self, pt: glam::IVec2, step: i32) -> impl Iterator<Item = glam::IVec2> + '_ {
+        NEIGHBOR_OFFSETS
             .iter()
-            .map(|offset| current_cell + *offset)
-            .filter(|new_pos| {
-                new_pos.x >= 1.
-                    && new_pos.x < maze_size - 1.
-                    && new_pos.y >= 1.
-                    && new_pos.y < maze_size - 1.
-            })
-            .filter(|new_pos| {
-                maze_image.get_pixel(new_pos.x as u32, new_pos.y as u32) == color_white
-            })
-            .collect::<Vec<mq::Vec2>>();
+            .map(move |offset| pt + *offset * step)
+            .filter(move |new_pt| self.in_bounds(*new_pt))
+    }
+    pub fn iter(&self) -> impl Iterator<Item = (glam::IVec2, Tile)> + '_ {
+        self.tiles.iter().enumerate().map(move |(i, tile)| {
+            let i = i as u32;
+            (
+                glam::ivec2((i % self.width) as i32, (i / self.width) as i32),
+                *tile,
+            )
+        })
+    }
+    pub fn window(&self, min: glam::IVec2, width: u32, height: u32) -> MazeGrid {
+        // tiles past the edge come back as walls
+        let mut grid = MazeGrid::new(width, height, Tile::Wall);
+        for y in 0..height as i32 {
+            for x in 0..width as i32 {
+                if let Some(tile) = self.get(min + glam::ivec2(x, y)) {
+                    grid.set(glam::ivec2(x, y), tile);
+                }
+            }
+        }
+        grid
+    }
+    pub fn from_image(image: &image::RgbaImage, wall_color: [u8; 4]) -> MazeGrid {
+        let mut grid = MazeGrid::new(image.width(), image.height(), Tile::Floor);
+        for (i, px) in image.pixels().enumerate() {
+            if px.0 == wall_color {
+                grid.tiles[i] = Tile::Wall;
+            }
+        }
+        grid
+    }
+    pub fn to_image(&self, wall_color: [u8; 4], floor_color: [u8; 4]) -> image::RgbaImage {
+        image::RgbaImage::from_fn(self.width, self.height, |x, y| {
+            if self.is_open(glam::ivec2(x as i32, y as i32)) {
+                image::Rgba(floor_color)
+            } else {
+                image::Rgba(wall_color)
+            }
+        })
+    }
+}
 
-        if !offset_locs.is_empty() {
-            stack.push(current_cell);
-            if first {
-                first = false;
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct CellLayout {
+    // maze cells sit on every other tile, the tiles between them are passages
+    pub origin: glam::IVec2,
+    pub cols: i32,
+    pub rows: i32,
+    pub mask: Option<Vec<bool>>, // by cell index, cells outside it are left as wall
+}
+impl CellLayout {
+    pub fn new(maze_size: u32, maze_start: glam::IVec2) -> CellLayout {
+        // cells share the parity of the start tile and stay off the outer border
+        let first = |start: i32| if start % 2 == 1 { 1 } else { 2 };
+        let last = maze_size as i32 - 2;
+        let count = |first: i32| {
+            if last < first {
+                0
             } else {
-                maze_image.set_pixel(current_cell.x as u32, current_cell.y as u32, color_black);
+                (last - first) / 2 + 1
             }
+        };
+        let origin = glam::ivec2(first(maze_start.x), first(maze_start.y));
+        CellLayout {
+            origin,
+            cols: count(origin.x),
+            rows: count(origin.y),
+            mask: None,
+        }
+    }
+    pub fn len(&self) -> usize {
+        // every index, masked out or not, so it can size per cell lists
+        (self.cols * self.rows) as usize
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    pub fn contains(&self, cell: glam::IVec2) -> bool {
+        cell.x >= 0
+            && cell.y >= 0
+            && cell.x < self.cols
+            && cell.y < self.rows
+            && self.mask.as_ref().is_none_or(|mask| mask[self.idx(cell)])
+    }
+    pub fn idx(&self, cell: glam::IVec2) -> usize {
+        (cell.y * self.cols + cell.x) as usize
+    }
+    pub fn cell_at(&self, idx: usize) -> glam::IVec2 {
+        glam::ivec2(idx as i32 % self.cols, idx as i32 / self.cols)
+    }
+    pub fn cells(&self) -> impl Iterator<Item = glam::IVec2> + '_ {
+        (0..self.len())
+            .map(move |idx| self.cell_at(idx))
+            .filter(move |cell| self.contains(*cell))
+    }
+    pub fn to_tile(&self, cell: glam::IVec2) -> glam::IVec2 {
+        self.origin + cell * 2
+    }
+    pub fn to_cell(&self, tile: glam::IVec2) -> Option<glam::IVec2> {
+        let d = tile - self.origin;
+        let cell = d / 2;
+        if d.x % 2 == 0 && d.y % 2 == 0 && d.x >= 0 && d.y >= 0 && self.contains(cell) {
+            Some(cell)
+        } else {
+            None
+        }
+    }
+    pub fn neighbors(&self, cell: glam::IVec2) -> impl Iterator<Item = glam::IVec2> + '_ {
+        NEIGHBOR_OFFSETS
+            .iter()
+            .map(move |offset| cell + *offset)
+            .filter(move |new_cell| self.contains(*new_cell))
+    }
+    pub fn is_carved(&self, grid: &MazeGrid, cell: glam::IVec2) -> bool {
+        grid.is_open(self.to_tile(cell))
+    }
+    pub fn carve_cell(&self, grid: &mut MazeGrid, cell: glam::IVec2) {
+        let revision = grid.revision;
+        carve_tile(grid, self.to_tile(cell));
+        grid.push_step(revision, CarveStep::Cell(cell));
+    }
+    pub fn carve_passage(&self, grid: &mut MazeGrid, a: glam::IVec2, b: glam::IVec2) {
+        // a and b must be neighbors
+        let revision = grid.revision;
+        let (a_tile, b_tile) = (self.to_tile(a), self.to_tile(b));
+        carve_tile(grid, a_tile);
+        carve_tile(grid, (a_tile + b_tile) / 2);
+        carve_tile(grid, b_tile);
+        grid.push_step(revision, CarveStep::Passage(a, b));
+    }
+    pub fn carve(&self, grid: &mut MazeGrid, step: CarveStep) {
+        match step {
+            CarveStep::Cell(cell) => self.carve_cell(grid, cell),
+            CarveStep::Passage(a, b) => self.carve_passage(grid, a, b),
+        }
+    }
+    pub fn passage_open(&self, grid: &MazeGrid, a: glam::IVec2, b: glam::IVec2) -> bool {
+        grid.is_open((self.to_tile(a) + self.to_tile(b)) / 2)
+    }
+}
 
-            let offset_loc = offset_locs[mq::rand::gen_range(0, offset_locs.len())];
-            let offset = offset_loc - current_cell;
+fn carve_tile(grid: &mut MazeGrid, tile: glam::IVec2) {
+    // leaves room floor alone so generators can run around placed rooms
+    if grid.is_wall(tile) {
+        grid.set(tile, Tile::Floor);
+    }
+}
 
-            let new_pos = current_cell + offset;
-            stack.push(new_pos);
-            let wall_pos = current_cell + offset / 2.;
-            maze_image.set_pixel(new_pos.x as u32, new_pos.y as u32, color_black);
-            maze_image.set_pixel(wall_pos.x as u32, wall_pos.y as u32, color_black);
-        }
+pub fn create_rng(seed: u64) -> quad_rand::RandGenerator {
+    let rng = quad_rand::RandGenerator::new();
+    rng.srand(seed);
+    rng
+}
+
+pub fn shuffle<T>(items: &mut [T], rng: &quad_rand::RandGenerator) {
+    // quad_rand's own shuffle never leaves an item in place, so roll our own
+    for i in (1..items.len()).rev() {
+        items.swap(i, rng.gen_range(0, i + 1));
     }
-    // maze_image.set_pixel(MAZE_START.x as u32, MAZE_START.y as u32, mq::BLUE);
-    // maze_image.export_png("maze.png");
+}
 
-    maze_image
+pub fn pick_sorted(
+    tiles: impl IntoIterator<Item = glam::IVec2>,
+    rng: &quad_rand::RandGenerator,
+) -> Option<glam::IVec2> {
+    // tiles tend to come out of a HashMap or HashSet, whose order changes from run to run, so
+    // they're sorted first or the same seed would roll a different tile
+    // return: None when there are no tiles, without rolling
+    let mut tiles = tiles.into_iter().collect::<Vec<glam::IVec2>>();
+    if tiles.is_empty() {
+        return None;
+    }
+    tiles.sort_by_key(|tile| (tile.y, tile.x));
+    Some(tiles[rng.gen_range(0, tiles.len())])
 }
 
-pub fn create_maze_texture(
-    maze_map: &mq::Image,
+pub fn create_maze_map(
     maze_size: f32,
-    maze_tile_size: f32,
-    color_white: mq::Color,
-    color_black: mq::Color,
-) -> mq::Image {
-    let mut maze_texture = mq::Image::gen_image_color(
-        maze_size as u16 * maze_tile_size as u16,
-        maze_size as u16 * maze_tile_size as u16,
-        color_white,
-    );
-    for x in 0..maze_size as u32 {
-        for y in 0..maze_size as u32 {
-            if maze_map.get_pixel(x, y) == color_black {
-                for tile_x in 0..maze_tile_size as u32 {
-                    for tile_y in 0..maze_tile_size as u32 {
-                        // if mq::rand::gen_range(0, 400) != 0 {
-                        //     maze_texture.set_pixel(
-                        //         x * maze_tile_size as u32 + tile_x,
-                        //         y * maze_tile_size as u32 + tile_y,
-                        //         color_black,
-                        //     );
-                        // }
-                        maze_texture.set_pixel(
-                            x * maze_tile_size as u32 + tile_x,
-                            y * maze_tile_size as u32 + tile_y,
-                            color_black,
-                        );
-                    }
-                }
-            } else {
-                for tile_x in 0..maze_tile_size as u32 {
-                    for tile_y in 0..maze_tile_size as u32 {
-                        if tile_x != 0
-                            && tile_x != (maze_tile_size - 1.) as u32
-                            && tile_y != 0
-                            && tile_y != (maze_tile_size - 1.) as u32
-                            && (tile_x == 1
-                                || tile_x as i32 == maze_tile_size as i32 - 2
-                                || tile_y == 1
-                                || tile_y as i32 == maze_tile_size as i32 - 2)
-                        {
-                            maze_texture.set_pixel(
-                                x * maze_tile_size as u32 + tile_x,
-                                y * maze_tile_size as u32 + tile_y,
-                                color_black,
-                            );
-                        }
+    maze_start: glam::Vec2,
+    seed: u64,
+    generator: &dyn MazeGenerator,
+) -> MazeGrid {
+    let rng = create_rng(seed);
+    let layout = CellLayout::new(maze_size as u32, maze_start.as_ivec2());
+    let start = layout.to_cell(maze_start.as_ivec2()).unwrap();
+
+    let mut maze_grid = MazeGrid::new(maze_size as u32, maze_size as u32, Tile::Wall);
+    generator.generate(&mut maze_grid, &layout, start, &rng);
+
+    maze_grid
+}
+
+pub struct MazeSteps {
+    // create_maze_map one carve at a time, for watching a maze get dug
+    pub layout: CellLayout,
+    grid: MazeGrid,
+    steps: VecDeque<CarveStep>,
+}
+impl MazeSteps {
+    pub fn new(
+        maze_size: f32,
+        maze_start: glam::Vec2,
+        seed: u64,
+        generator: &dyn MazeGenerator,
+    ) -> MazeSteps {
+        // the generator runs to the end up front and its carves are replayed from the log
+        let rng = create_rng(seed);
+        let layout = CellLayout::new(maze_size as u32, maze_start.as_ivec2());
+        let start = layout.to_cell(maze_start.as_ivec2()).unwrap();
+
+        let mut finished = MazeGrid::new(maze_size as u32, maze_size as u32, Tile::Wall);
+        finished.record_steps();
+        generator.generate(&mut finished, &layout, start, &rng);
+
+        MazeSteps {
+            layout,
+            grid: MazeGrid::new(maze_size as u32, maze_size as u32, Tile::Wall),
+            steps: finished.take_steps().into(),
+        }
+    }
+    pub fn grid(&self) -> &MazeGrid {
+        // the maze as of the last step
+        &self.grid
+    }
+    pub fn remaining(&self) -> usize {
+        self.steps.len()
+    }
+}
+impl Iterator for MazeSteps {
+    type Item = CarveStep;
+    fn next(&mut self) -> Option<CarveStep> {
+        let step = self.steps.pop_front()?;
+        self.layout.carve(&mut self.grid, step);
+        Some(step)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::maze_generators;
+
+    const GENERATORS: [&str; 6] = [
+        "backtracker",
+        "prim",
+        "kruskal",
+        "wilson",
+        "eller",
+        "growing-tree:mixed:0.5",
+    ];
+    const SIZE: f32 = 21.;
+    const START: glam::Vec2 = glam::vec2(11., 11.);
+
+    fn regions(grid: &MazeGrid, layout: &CellLayout) -> (usize, usize) {
+        // return: (groups of carved cells joined by passages, open passages)
+        let carved = layout
+            .cells()
+            .filter(|cell| layout.is_carved(grid, *cell))
+            .collect::<Vec<glam::IVec2>>();
+        let mut seen = vec![false; layout.len()];
+        let mut groups = 0;
+        for cell in carved.iter() {
+            if seen[layout.idx(*cell)] {
+                continue;
+            }
+            groups += 1;
+            seen[layout.idx(*cell)] = true;
+            let mut stack = vec![*cell];
+            while let Some(cell) = stack.pop() {
+                for new_cell in layout.neighbors(cell) {
+                    if !seen[layout.idx(new_cell)] && layout.passage_open(grid, cell, new_cell) {
+                        seen[layout.idx(new_cell)] = true;
+                        stack.push(new_cell);
                     }
                 }
             }
         }
+        let passages = carved
+            .iter()
+            .flat_map(|cell| {
+                layout
+                    .neighbors(*cell)
+                    .map(move |new_cell| (*cell, new_cell))
+            })
+            .filter(|(cell, new_cell)| layout.passage_open(grid, *cell, *new_cell))
+            .count()
+            / 2;
+        (groups, passages)
     }
 
-    maze_texture
+    #[test]
+    fn get_and_set_stay_in_bounds() {
+        let mut grid = MazeGrid::new(3, 2, Tile::Wall);
+        assert_eq!(grid.get(glam::ivec2(2, 1)), Some(Tile::Wall));
+        for pt in [
+            glam::ivec2(-1, 0),
+            glam::ivec2(0, -1),
+            glam::ivec2(3, 0),
+            glam::ivec2(0, 2),
+        ] {
+            assert_eq!(grid.get(pt), None);
+            assert!(!grid.set(pt, Tile::Floor));
+            assert!(!grid.is_wall(pt) && !grid.is_open(pt));
+        }
+        assert_eq!(grid.revision(), 0);
+
+        assert!(grid.set(glam::ivec2(2, 1), Tile::Floor));
+        assert_eq!(grid.get(glam::ivec2(2, 1)), Some(Tile::Floor));
+        assert!(grid.is_open(glam::ivec2(2, 1)));
+        assert_eq!(grid.revision(), 1);
+        // setting a tile to what it already is isn't a change
+        grid.set(glam::ivec2(2, 1), Tile::Floor);
+        assert_eq!(grid.revision(), 1);
+    }
+
+    #[test]
+    fn neighbors_skip_out_of_bounds() {
+        let grid = MazeGrid::new(5, 5, Tile::Wall);
+        let corner = grid.neighbors(glam::ivec2(0, 0), 1).collect::<Vec<_>>();
+        assert_eq!(corner, vec![glam::ivec2(1, 0), glam::ivec2(0, 1)]);
+        let middle = grid.neighbors(glam::ivec2(2, 2), 2).collect::<Vec<_>>();
+        assert_eq!(
+            middle,
+            vec![
+                glam::ivec2(2, 0),
+                glam::ivec2(4, 2),
+                glam::ivec2(2, 4),
+                glam::ivec2(0, 2)
+            ]
+        );
+        assert_eq!(grid.neighbors(glam::ivec2(3, 3), 2).count(), 2);
+        assert_eq!(grid.neighbors(glam::ivec2(2, 2), 3).count(), 0);
+    }
+
+    #[test]
+    fn image_round_trip() {
+        let (wall, floor) = ([255, 255, 255, 255], [0, 0, 0, 255]);
+        let mut grid = MazeGrid::new(4, 3, Tile::Wall);
+        for pt in [glam::ivec2(1, 1), glam::ivec2(2, 1), glam::ivec2(3, 2)] {
+            grid.set(pt, Tile::Floor);
+        }
+        let image = grid.to_image(wall, floor);
+        assert_eq!((image.width(), image.height()), (4, 3));
+        assert_eq!(image.get_pixel(1, 1).0, floor);
+        assert_eq!(image.get_pixel(0, 0).0, wall);
+        assert_eq!(MazeGrid::from_image(&image, wall), grid);
+    }
+
+    #[test]
+    fn pick_sorted_ignores_the_order() {
+        let tiles = (0..20)
+            .map(|i| glam::ivec2(i % 5, i / 5))
+            .collect::<Vec<_>>();
+        for seed in 0..10 {
+            let picked = pick_sorted(tiles.iter().copied(), &create_rng(seed));
+            let reversed = pick_sorted(tiles.iter().rev().copied(), &create_rng(seed));
+            assert_eq!(picked, reversed);
+        }
+        assert_eq!(pick_sorted([], &create_rng(0)), None);
+    }
+
+    #[test]
+    fn steps_replay_to_the_same_maze() {
+        for name in GENERATORS {
+            let generator = maze_generators::parse_generator(name).unwrap();
+            let mut steps = MazeSteps::new(SIZE, START, 4, generator.as_ref());
+            let total = steps.remaining();
+            assert!(total > 0);
+            assert_eq!(steps.by_ref().count(), total, "{}", name);
+            assert_eq!(steps.remaining(), 0);
+            let expected = create_maze_map(SIZE, START, 4, generator.as_ref());
+            assert_eq!(steps.grid(), &expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn every_step_keeps_the_maze_a_tree() {
+        // no step ever closes a loop, so every group of cells is a tree, and generators that
+        // grow out from the start never have more than one group
+        for name in GENERATORS {
+            let generator = maze_generators::parse_generator(name).unwrap();
+            let mut steps = MazeSteps::new(SIZE, START, 5, generator.as_ref());
+            let layout = steps.layout.clone();
+            // kruskal and eller join groups up, wilson carves each walk from its far end
+            let grows = !matches!(name, "kruskal" | "eller" | "wilson");
+            while let Some(step) = steps.next() {
+                let carved = layout
+                    .cells()
+                    .filter(|cell| layout.is_carved(steps.grid(), *cell))
+                    .count();
+                let (groups, passages) = regions(steps.grid(), &layout);
+                assert_eq!(passages, carved - groups, "{} loop at {:?}", name, step);
+                if grows {
+                    assert_eq!(groups, 1, "{} split at {:?}", name, step);
+                }
+            }
+            // every cell ends up carved and joined
+            let (groups, passages) = regions(steps.grid(), &layout);
+            assert_eq!(
+                (groups, passages),
+                (1, layout.cells().count() - 1),
+                "{}",
+                name
+            );
+        }
+    }
 }
@@ -0,0 +1,301 @@
+use crate::camera_manager::CameraManager;
+use crate::maze::Tile;
+use crate::maze_chunks::ChunkedMaze;
+use macroquad::prelude as mq;
+
+use std::collections::HashMap;
+use std::fmt;
+
+// neighbor bits, the first 4 match maze::NEIGHBOR_OFFSETS
+const NEIGHBOR_BITS: [(&str, mq::IVec2); 8] = [
+    ("n", mq::ivec2(0, -1)),
+    ("e", mq::ivec2(1, 0)),
+    ("s", mq::ivec2(0, 1)),
+    ("w", mq::ivec2(-1, 0)),
+    ("ne", mq::ivec2(1, -1)),
+    ("se", mq::ivec2(1, 1)),
+    ("sw", mq::ivec2(-1, 1)),
+    ("nw", mq::ivec2(-1, -1)),
+];
+const EDGE_MASK: u8 = 0b1111;
+
+fn tile_name(tile: Tile) -> &'static str {
+    match tile {
+        Tile::Wall => "wall",
+        Tile::Floor => "floor",
+        Tile::Room => "room",
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum AtlasDefError {
+    MissingTileSize,
+    BadLine { line: usize, text: String },
+    MissingDefault(&'static str),
+}
+impl fmt::Display for AtlasDefError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AtlasDefError::MissingTileSize => write!(f, "no 'tile_size <px>' line"),
+            AtlasDefError::BadLine { line, text } => write!(
+                f,
+                "line {}: '{}' should be '<wall|floor|room> <n+e+...|-> <col> <row>'",
+                line + 1,
+                text
+            ),
+            AtlasDefError::MissingDefault(name) => {
+                write!(f, "{} has no '-' variant to fall back on", name)
+            }
+        }
+    }
+}
+impl std::error::Error for AtlasDefError {}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct AtlasDef {
+    pub tile_size: f32, // in atlas pixels
+    variants: HashMap<(&'static str, u8), (u32, u32)>,
+}
+impl AtlasDef {
+    pub fn parse(text: &str) -> Result<AtlasDef, AtlasDefError> {
+        // tile_size <px>
+        // <wall|floor|room> <neighbors of the same kind, like n+e+ne, or - for none> <col> <row>
+        let mut tile_size = None;
+        let mut variants = HashMap::new();
+        for (line, text) in text.lines().enumerate() {
+            let words = text.split_whitespace().collect::<Vec<&str>>();
+            let bad_line = || AtlasDefError::BadLine {
+                line,
+                text: text.to_owned(),
+            };
+            match words.as_slice() {
+                [] => {}
+                [comment, ..] if comment.starts_with("//") => {}
+                ["tile_size", size] => tile_size = Some(size.parse().map_err(|_| bad_line())?),
+                [name, neighbors, col, row] => {
+                    let name = [Tile::Wall, Tile::Floor, Tile::Room]
+                        .into_iter()
+                        .map(tile_name)
+                        .find(|tile_name| tile_name == name)
+                        .ok_or_else(bad_line)?;
+                    let mut mask = 0;
+                    if *neighbors != "-" {
+                        for neighbor in neighbors.split('+') {
+                            let bit = NEIGHBOR_BITS
+                                .iter()
+                                .position(|(bit_name, _)| *bit_name == neighbor)
+                                .ok_or_else(bad_line)?;
+                            mask |= 1 << bit;
+                        }
+                    }
+                    let col = col.parse().map_err(|_| bad_line())?;
+                    let row = row.parse().map_err(|_| bad_line())?;
+                    variants.insert((name, mask), (col, row));
+                }
+                _ => return Err(bad_line()),
+            }
+        }
+
+        for tile in [Tile::Wall, Tile::Floor, Tile::Room] {
+            if !variants.contains_key(&(tile_name(tile), 0)) {
+                return Err(AtlasDefError::MissingDefault(tile_name(tile)));
+            }
+        }
+        Ok(AtlasDef {
+            tile_size: tile_size.ok_or(AtlasDefError::MissingTileSize)?,
+            variants,
+        })
+    }
+    pub fn variant(&self, tile: Tile, mask: u8) -> (u32, u32) {
+        // exact 8 neighbor art first, then the 4 neighbor art, then the plain tile
+        let name = tile_name(tile);
+        [mask, mask & EDGE_MASK, 0]
+            .iter()
+            .find_map(|mask| self.variants.get(&(name, *mask)))
+            .copied()
+            .unwrap()
+    }
+}
+
+pub fn neighbor_mask(maze: &ChunkedMaze, tile_pt: mq::IVec2) -> u8 {
+    // bits are set for neighbors of the same kind, walls join walls and open tiles join open
+    // tiles, anything unloaded counts as wall
+    let open = |pt: mq::IVec2| maze.get(pt).is_some_and(|tile| tile.is_open());
+    let is_open = open(tile_pt);
+    let mut mask = NEIGHBOR_BITS
+        .iter()
+        .enumerate()
+        .filter(|(_, (_, offset))| open(tile_pt + *offset) == is_open)
+        .fold(0, |mask, (bit, _)| mask | 1 << bit);
+    // a corner only changes the art when both edges beside it are set
+    for corner in 4..8 {
+        let edges = 1 << (corner - 4) | 1 << ((corner - 3) % 4);
+        if mask & edges != edges {
+            mask &= !(1 << corner);
+        }
+    }
+    mask
+}
+
+pub struct TileAtlas {
+    pub texture: mq::Texture2D,
+    pub def: AtlasDef,
+}
+impl TileAtlas {
+    pub fn new(texture: mq::Texture2D, def: AtlasDef) -> TileAtlas {
+        texture.set_filter(mq::FilterMode::Nearest);
+        TileAtlas { texture, def }
+    }
+    pub fn source(&self, tile: Tile, mask: u8) -> mq::Rect {
+        let (col, row) = self.def.variant(tile, mask);
+        mq::Rect::new(
+            col as f32 * self.def.tile_size,
+            row as f32 * self.def.tile_size,
+            self.def.tile_size,
+            self.def.tile_size,
+        )
+    }
+    pub fn draw_tile(&self, tile: Tile, mask: u8, pt: mq::Vec2, size: f32) {
+        mq::draw_texture_ex(
+            self.texture,
+            pt.x,
+            pt.y,
+            mq::WHITE,
+            mq::DrawTextureParams {
+                dest_size: Some(mq::Vec2::splat(size)),
+                source: Some(self.source(tile, mask)),
+                ..Default::default()
+            },
+        );
+    }
+}
+
+pub fn visible_tiles(
+    cm: &CameraManager,
+    maze_pt: mq::Vec2,
+    view_size: mq::Vec2,
+    tile_size: f32,
+) -> (mq::IVec2, mq::IVec2) {
+    // return: (top left, bottom right) tiles touching the view, both included
+    let min = (cm.pt - maze_pt) / tile_size;
+    let max = (cm.pt + view_size - maze_pt) / tile_size;
+    (min.floor().as_ivec2(), max.floor().as_ivec2())
+}
+
+pub fn draw_maze(
+    maze: &ChunkedMaze,
+    atlas: &TileAtlas,
+    cm: &CameraManager,
+    maze_pt: mq::Vec2,
+    view_size: mq::Vec2,
+    tile_size: f32,
+) {
+    // unloaded tiles are left as background
+    let (min, max) = visible_tiles(cm, maze_pt, view_size, tile_size);
+    for y in min.y..=max.y {
+        for x in min.x..=max.x {
+            let tile_pt = mq::ivec2(x, y);
+            if let Some(tile) = maze.get(tile_pt) {
+                atlas.draw_tile(
+                    tile,
+                    neighbor_mask(maze, tile_pt),
+                    cm.calc_offset(maze_pt + tile_pt.as_vec2() * tile_size),
+                    tile_size,
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::maze::MazeGrid;
+
+    const SHIPPED: &str = include_str!("../assets/maze_atlas.txt");
+
+    fn reduced(mask: u8) -> u8 {
+        // the corner rule spelled out: (corner, edge, edge)
+        let mut mask = mask;
+        for (corner, a, b) in [(4, 0, 1), (5, 1, 2), (6, 2, 3), (7, 3, 0)] {
+            if mask & (1 << a) == 0 || mask & (1 << b) == 0 {
+                mask &= !(1 << corner);
+            }
+        }
+        mask
+    }
+
+    #[test]
+    fn parses_the_shipped_atlas() {
+        let def = AtlasDef::parse(SHIPPED).unwrap();
+        assert_eq!(def.tile_size, 40.);
+        assert_eq!(def.variant(Tile::Wall, 0), (0, 0));
+        assert_eq!(def.variant(Tile::Wall, 0b1), (1, 0));
+        let count = |name| def.variants.keys().filter(|(n, _)| *n == name).count();
+        assert_eq!((count("wall"), count("floor"), count("room")), (47, 16, 1));
+        // every wall neighbor_mask can give has its own art
+        for mask in 0..=255 {
+            assert!(def.variants.contains_key(&("wall", reduced(mask))));
+        }
+        // and floors fall back to their 4 neighbor art
+        assert_eq!(
+            def.variant(Tile::Floor, 0b1111_0101),
+            def.variant(Tile::Floor, 0b0101)
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_lines() {
+        let bad_line = |text: &str| {
+            let atlas = format!(
+                "tile_size 40\nwall - 0 0\nfloor - 1 0\nroom - 2 0\n{}\n",
+                text
+            );
+            assert_eq!(
+                AtlasDef::parse(&atlas),
+                Err(AtlasDefError::BadLine {
+                    line: 4,
+                    text: text.to_owned()
+                })
+            );
+        };
+        bad_line("lava - 0 0");
+        bad_line("wall n+up 1 0");
+        bad_line("wall n 1");
+        bad_line("wall n 1 0 extra");
+        bad_line("wall n one 0");
+        bad_line("tile_size big");
+
+        assert_eq!(
+            AtlasDef::parse("tile_size 40\nwall - 0 0\nroom - 2 0\n"),
+            Err(AtlasDefError::MissingDefault("floor"))
+        );
+        assert_eq!(
+            AtlasDef::parse("wall - 0 0\nfloor - 1 0\nroom - 2 0\n"),
+            Err(AtlasDefError::MissingTileSize)
+        );
+    }
+
+    #[test]
+    fn masks_set_a_bit_for_each_neighbor_of_the_same_kind() {
+        for center in [Tile::Wall, Tile::Floor] {
+            for neighbors in 0..=255u8 {
+                let mut grid = MazeGrid::new(3, 3, Tile::Wall);
+                grid.set(mq::ivec2(1, 1), center);
+                for (bit, (_, offset)) in NEIGHBOR_BITS.iter().enumerate() {
+                    if neighbors & (1 << bit) != 0 {
+                        grid.set(mq::ivec2(1, 1) + *offset, Tile::Floor);
+                    }
+                }
+                let mut maze = ChunkedMaze::from_grid(grid, 16);
+                maze.load(mq::ivec2(0, 0));
+                let same = if center == Tile::Wall {
+                    !neighbors
+                } else {
+                    neighbors
+                };
+                assert_eq!(neighbor_mask(&maze, mq::ivec2(1, 1)), reduced(same));
+            }
+        }
+    }
+}
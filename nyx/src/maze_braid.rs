@@ -0,0 +1,206 @@
+use crate::maze::{self, CellLayout, MazeGrid};
+
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub struct BraidConfig {
+    pub dead_end_chance: f32, // chance each dead end gets knocked through
+    pub loop_chance: f32,     // chance each remaining wall between two cells gets opened
+}
+impl BraidConfig {
+    pub fn new(dead_end_chance: f32, loop_chance: f32) -> BraidConfig {
+        BraidConfig {
+            dead_end_chance,
+            loop_chance,
+        }
+    }
+    pub fn is_noop(&self) -> bool {
+        self.dead_end_chance <= 0. && self.loop_chance <= 0.
+    }
+}
+impl Default for BraidConfig {
+    fn default() -> BraidConfig {
+        // a perfect maze, untouched
+        BraidConfig::new(0., 0.)
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Default)]
+pub struct BraidStats {
+    pub dead_ends_before: usize,
+    pub dead_ends_after: usize,
+    // every wall knocked between two connected cells closes exactly one loop
+    pub loops_added: usize,
+}
+impl BraidStats {
+    pub fn add(&mut self, other: BraidStats) {
+        self.dead_ends_before += other.dead_ends_before;
+        self.dead_ends_after += other.dead_ends_after;
+        self.loops_added += other.loops_added;
+    }
+}
+
+pub fn is_dead_end(grid: &MazeGrid, tile: glam::IVec2) -> bool {
+    grid.is_open(tile)
+        && grid
+            .neighbors(tile, 1)
+            .filter(|new_tile| grid.is_open(*new_tile))
+            .count()
+            == 1
+}
+
+pub fn dead_ends(grid: &MazeGrid, layout: &CellLayout) -> Vec<glam::IVec2> {
+    layout
+        .cells()
+        .filter(|cell| is_dead_end(grid, layout.to_tile(*cell)))
+        .collect()
+}
+
+fn closed_neighbors(grid: &MazeGrid, layout: &CellLayout, cell: glam::IVec2) -> Vec<glam::IVec2> {
+    // carved neighbor cells with a wall between them and cell
+    layout
+        .neighbors(cell)
+        .filter(|new_cell| layout.is_carved(grid, *new_cell))
+        .filter(|new_cell| !layout.passage_open(grid, cell, *new_cell))
+        .collect()
+}
+
+pub fn braid(
+    grid: &mut MazeGrid,
+    layout: &CellLayout,
+    config: BraidConfig,
+    rng: &quad_rand::RandGenerator,
+) -> BraidStats {
+    let mut ends = dead_ends(grid, layout);
+    let mut stats = BraidStats {
+        dead_ends_before: ends.len(),
+        dead_ends_after: ends.len(),
+        loops_added: 0,
+    };
+    if config.is_noop() {
+        return stats;
+    }
+    maze::shuffle(&mut ends, rng);
+
+    for cell in ends {
+        // an earlier knock through may have already fixed this one
+        if !is_dead_end(grid, layout.to_tile(cell))
+            || rng.gen_range(0., 1.) >= config.dead_end_chance
+        {
+            continue;
+        }
+        let options = closed_neighbors(grid, layout, cell);
+        if options.is_empty() {
+            continue;
+        }
+        // joining two dead ends at once gets rid of both
+        let best = options
+            .iter()
+            .copied()
+            .filter(|new_cell| is_dead_end(grid, layout.to_tile(*new_cell)))
+            .collect::<Vec<glam::IVec2>>();
+        let new_cell = if best.is_empty() {
+            options[rng.gen_range(0, options.len())]
+        } else {
+            best[rng.gen_range(0, best.len())]
+        };
+        layout.carve_passage(grid, cell, new_cell);
+        stats.loops_added += 1;
+    }
+
+    if config.loop_chance > 0. {
+        for cell in layout.cells() {
+            // only look right and down so every wall is rolled once
+            for offset in [glam::ivec2(1, 0), glam::ivec2(0, 1)] {
+                let new_cell = cell + offset;
+                if closed_neighbors(grid, layout, cell).contains(&new_cell)
+                    && rng.gen_range(0., 1.) < config.loop_chance
+                {
+                    layout.carve_passage(grid, cell, new_cell);
+                    stats.loops_added += 1;
+                }
+            }
+        }
+    }
+
+    stats.dead_ends_after = dead_ends(grid, layout).len();
+    stats
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::maze_generators::RecursiveBacktracker;
+
+    const SIZE: f32 = 21.;
+    const START: glam::Vec2 = glam::vec2(11., 11.);
+
+    fn perfect_maze(seed: u64) -> (MazeGrid, CellLayout) {
+        let grid = maze::create_maze_map(SIZE, START, seed, &RecursiveBacktracker);
+        (grid, CellLayout::new(SIZE as u32, START.as_ivec2()))
+    }
+
+    fn open_passages(grid: &MazeGrid, layout: &CellLayout) -> usize {
+        layout
+            .cells()
+            .flat_map(|cell| {
+                [cell + glam::ivec2(1, 0), cell + glam::ivec2(0, 1)].map(|n| (cell, n))
+            })
+            .filter(|(cell, n)| layout.contains(*n) && layout.passage_open(grid, *cell, *n))
+            .count()
+    }
+
+    #[test]
+    fn noop_leaves_the_maze_alone() {
+        let (mut grid, layout) = perfect_maze(1);
+        let before = grid.clone();
+        let stats = braid(
+            &mut grid,
+            &layout,
+            BraidConfig::default(),
+            &maze::create_rng(1),
+        );
+        assert_eq!(grid, before);
+        assert_eq!(stats.loops_added, 0);
+        assert_eq!(stats.dead_ends_before, stats.dead_ends_after);
+        assert_eq!(stats.dead_ends_before, dead_ends(&grid, &layout).len());
+    }
+
+    #[test]
+    fn full_braid_removes_every_dead_end() {
+        let (mut grid, layout) = perfect_maze(2);
+        let stats = braid(
+            &mut grid,
+            &layout,
+            BraidConfig::new(1., 0.),
+            &maze::create_rng(2),
+        );
+        assert!(stats.dead_ends_before > 0);
+        assert_eq!(stats.dead_ends_after, 0);
+        assert!(dead_ends(&grid, &layout).is_empty());
+    }
+
+    #[test]
+    fn each_loop_is_one_more_passage() {
+        // a perfect maze has one passage less than it has cells
+        for seed in 0..5 {
+            let (mut grid, layout) = perfect_maze(seed);
+            let cells = layout.cells().count();
+            assert_eq!(open_passages(&grid, &layout), cells - 1);
+            let config = BraidConfig::new(0.5, 0.1);
+            let stats = braid(&mut grid, &layout, config, &maze::create_rng(seed));
+            assert!(stats.loops_added > 0);
+            assert_eq!(open_passages(&grid, &layout), cells - 1 + stats.loops_added);
+            assert_eq!(stats.dead_ends_after, dead_ends(&grid, &layout).len());
+        }
+    }
+
+    #[test]
+    fn same_seed_same_braid() {
+        let config = BraidConfig::new(0.5, 0.05);
+        let (mut a, layout) = perfect_maze(3);
+        let mut b = a.clone();
+        let a_stats = braid(&mut a, &layout, config, &maze::create_rng(9));
+        let b_stats = braid(&mut b, &layout, config, &maze::create_rng(9));
+        assert_eq!(a, b);
+        assert_eq!(a_stats, b_stats);
+    }
+}
@@ -0,0 +1,379 @@
+use crate::maze::{self, CellLayout, MazeGrid, Tile};
+use crate::maze_braid::{self, BraidConfig, BraidStats};
+use crate::maze_generators::MazeGenerator;
+use crate::maze_rooms::{self, Room, RoomConfig, RoomOrder};
+
+use std::collections::{HashMap, HashSet};
+
+const SEAM_SALT: u64 = 0x5EA5;
+const BRAID_SALT: u64 = 0xB8A1D;
+const ROOM_SALT: u64 = 0x800E;
+
+pub fn chunk_seed(seed: u64, chunk: glam::IVec2, salt: u64) -> u64 {
+    // splitmix64 over the world seed and chunk coordinate, same result on native and wasm
+    let mut z = seed
+        ^ (chunk.x as u32 as u64).wrapping_mul(0x9E3779B97F4A7C15)
+        ^ (chunk.y as u32 as u64).wrapping_mul(0xC2B2AE3D27D4EB4F)
+        ^ salt.wrapping_mul(0x165667B19E3779F9);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+    z ^ (z >> 31)
+}
+
+pub fn chunk_layout(chunk_size: i32) -> CellLayout {
+    // row 0 and column 0 are the seams shared with the chunks above and to the left
+    CellLayout {
+        origin: glam::ivec2(1, 1),
+        cols: chunk_size / 2,
+        rows: chunk_size / 2,
+        mask: None,
+    }
+}
+
+pub struct Chunk {
+    pub grid: MazeGrid,
+    pub rooms: Vec<Room>,
+    pub braid_stats: BraidStats,
+}
+
+pub fn create_chunk(
+    seed: u64,
+    chunk: glam::IVec2,
+    chunk_size: i32,
+    generator: &dyn MazeGenerator,
+    braid: BraidConfig,
+    room_config: &RoomConfig,
+) -> Chunk {
+    let layout = chunk_layout(chunk_size);
+    let start = glam::ivec2(layout.cols, layout.rows) / 2;
+    let mut grid = MazeGrid::new(chunk_size as u32, chunk_size as u32, Tile::Wall);
+    let room_rng = maze::create_rng(chunk_seed(seed, chunk, ROOM_SALT));
+
+    let mut rooms = vec![];
+    if room_config.order == RoomOrder::BeforeMaze {
+        rooms = maze_rooms::place_rooms(&mut grid, &layout, room_config, start, &room_rng);
+    }
+    generator.generate(
+        &mut grid,
+        &layout,
+        start,
+        &maze::create_rng(chunk_seed(seed, chunk, 0)),
+    );
+    if room_config.order == RoomOrder::AfterMaze {
+        rooms = maze_rooms::place_rooms(&mut grid, &layout, room_config, start, &room_rng);
+    }
+    maze_rooms::connect_regions(&mut grid, &layout, start, &room_rng);
+
+    let braid_stats = maze_braid::braid(
+        &mut grid,
+        &layout,
+        braid,
+        &maze::create_rng(chunk_seed(seed, chunk, BRAID_SALT)),
+    );
+
+    // one door through the top and left seams, neighbors own the bottom and right ones
+    let rng = maze::create_rng(chunk_seed(seed, chunk, SEAM_SALT));
+    grid.set(
+        glam::ivec2(1 + 2 * rng.gen_range(0, layout.cols), 0),
+        Tile::Floor,
+    );
+    grid.set(
+        glam::ivec2(0, 1 + 2 * rng.gen_range(0, layout.rows)),
+        Tile::Floor,
+    );
+
+    Chunk {
+        grid,
+        rooms,
+        braid_stats,
+    }
+}
+
+pub enum ChunkSource {
+    Generated(Box<dyn MazeGenerator>),
+    Fixed(MazeGrid), // a hand authored maze, everything past its edge is wall
+}
+impl ChunkSource {
+    pub fn name(&self) -> String {
+        match self {
+            ChunkSource::Generated(generator) => generator.name(),
+            ChunkSource::Fixed(_) => "custom".to_owned(),
+        }
+    }
+}
+
+#[derive(Default, Debug)]
+pub struct ChunkChanges {
+    pub loaded: Vec<glam::IVec2>,
+    pub unloaded: Vec<glam::IVec2>,
+}
+
+pub struct ChunkedMaze {
+    pub seed: u64,
+    pub chunk_size: i32,
+    pub source: ChunkSource,
+    pub braid: BraidConfig,
+    pub braid_stats: BraidStats, // summed over every chunk generated so far, once each
+    pub rooms: RoomConfig,
+    // (min, max) chunks, anything past them loads as solid wall, None goes on forever
+    pub bounds: Option<(glam::IVec2, glam::IVec2)>,
+    chunks: HashMap<glam::IVec2, Chunk>,
+    // chunks that have been generated before, a chunk loading again adds nothing to the stats
+    generated: HashSet<glam::IVec2>,
+    // tiles changed after generation, put back whenever their chunk loads again
+    overrides: HashMap<glam::IVec2, Tile>,
+    revision: u64, // bumped whenever chunks load or unload, or a tile is set
+}
+impl ChunkedMaze {
+    pub fn new(
+        seed: u64,
+        chunk_size: i32,
+        generator: Box<dyn MazeGenerator>,
+        braid: BraidConfig,
+        rooms: RoomConfig,
+    ) -> ChunkedMaze {
+        ChunkedMaze {
+            seed,
+            chunk_size,
+            source: ChunkSource::Generated(generator),
+            braid,
+            braid_stats: BraidStats::default(),
+            rooms,
+            bounds: None,
+            chunks: HashMap::new(),
+            generated: HashSet::new(),
+            overrides: HashMap::new(),
+            revision: 0,
+        }
+    }
+    pub fn from_grid(grid: MazeGrid, chunk_size: i32) -> ChunkedMaze {
+        // the grid's top left tile sits at world tile (0, 0)
+        ChunkedMaze {
+            seed: 0,
+            chunk_size,
+            source: ChunkSource::Fixed(grid),
+            braid: BraidConfig::default(),
+            braid_stats: BraidStats::default(),
+            rooms: RoomConfig::default(),
+            bounds: None,
+            chunks: HashMap::new(),
+            generated: HashSet::new(),
+            overrides: HashMap::new(),
+            revision: 0,
+        }
+    }
+    pub fn to_chunk(&self, tile: glam::IVec2) -> (glam::IVec2, glam::IVec2) {
+        // return: (chunk, tile inside the chunk)
+        let size = self.chunk_size;
+        (
+            glam::ivec2(tile.x.div_euclid(size), tile.y.div_euclid(size)),
+            glam::ivec2(tile.x.rem_euclid(size), tile.y.rem_euclid(size)),
+        )
+    }
+    pub fn in_bounds(&self, chunk: glam::IVec2) -> bool {
+        self.bounds
+            .is_none_or(|(min, max)| chunk.cmpge(min).all() && chunk.cmple(max).all())
+    }
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+    pub fn chunk(&self, chunk: glam::IVec2) -> Option<&Chunk> {
+        self.chunks.get(&chunk)
+    }
+    pub fn chunks(&self) -> impl Iterator<Item = (&glam::IVec2, &Chunk)> {
+        self.chunks.iter()
+    }
+    pub fn get(&self, tile: glam::IVec2) -> Option<Tile> {
+        let (chunk, local) = self.to_chunk(tile);
+        self.chunk(chunk).and_then(|chunk| chunk.grid.get(local))
+    }
+    pub fn is_wall(&self, tile: glam::IVec2) -> bool {
+        self.get(tile) == Some(Tile::Wall)
+    }
+    pub fn set(&mut self, tile: glam::IVec2, new_tile: Tile) -> bool {
+        // return: whether the tile's chunk is loaded, unloaded tiles can't be set
+        let (chunk, local) = self.to_chunk(tile);
+        match self.chunks.get_mut(&chunk) {
+            Some(chunk) => {
+                chunk.grid.set(local, new_tile);
+                self.overrides.insert(tile, new_tile);
+                self.revision += 1;
+                true
+            }
+            None => false,
+        }
+    }
+    pub fn window(&self, min: glam::IVec2, width: u32, height: u32) -> MazeGrid {
+        // stitches loaded chunks together, unloaded tiles come back as walls
+        let mut grid = MazeGrid::new(width, height, Tile::Wall);
+        for y in 0..height as i32 {
+            for x in 0..width as i32 {
+                if let Some(tile) = self.get(min + glam::ivec2(x, y)) {
+                    grid.set(glam::ivec2(x, y), tile);
+                }
+            }
+        }
+        grid
+    }
+    pub fn load(&mut self, chunk: glam::IVec2) -> bool {
+        // return: whether the chunk was newly generated
+        if self.chunks.contains_key(&chunk) {
+            return false;
+        }
+        let mut new_chunk = match &self.source {
+            _ if !self.in_bounds(chunk) => Chunk {
+                grid: MazeGrid::new(self.chunk_size as u32, self.chunk_size as u32, Tile::Wall),
+                rooms: vec![],
+                braid_stats: BraidStats::default(),
+            },
+            ChunkSource::Generated(generator) => create_chunk(
+                self.seed,
+                chunk,
+                self.chunk_size,
+                generator.as_ref(),
+                self.braid,
+                &self.rooms,
+            ),
+            ChunkSource::Fixed(grid) => Chunk {
+                grid: grid.window(
+                    chunk * self.chunk_size,
+                    self.chunk_size as u32,
+                    self.chunk_size as u32,
+                ),
+                rooms: vec![],
+                braid_stats: BraidStats::default(),
+            },
+        };
+        if let Some((min, _)) = self.bounds {
+            // the top and left seam doors on the edge would lead out into the wall
+            for i in 0..self.chunk_size {
+                if chunk.y == min.y {
+                    new_chunk.grid.set(glam::ivec2(i, 0), Tile::Wall);
+                }
+                if chunk.x == min.x {
+                    new_chunk.grid.set(glam::ivec2(0, i), Tile::Wall);
+                }
+            }
+        }
+        for (tile, override_tile) in self.overrides.iter() {
+            let (tile_chunk, local) = self.to_chunk(*tile);
+            if tile_chunk == chunk {
+                new_chunk.grid.set(local, *override_tile);
+            }
+        }
+        if self.generated.insert(chunk) {
+            self.braid_stats.add(new_chunk.braid_stats);
+        }
+        self.chunks.insert(chunk, new_chunk);
+        self.revision += 1;
+        true
+    }
+    pub fn update(
+        &mut self,
+        tile: glam::IVec2,
+        load_radius: i32,
+        unload_radius: i32,
+    ) -> ChunkChanges {
+        // unload_radius > load_radius keeps chunks from thrashing on a border
+        let (center, _) = self.to_chunk(tile);
+        let mut changes = ChunkChanges::default();
+
+        for y in -load_radius..=load_radius {
+            for x in -load_radius..=load_radius {
+                let chunk = center + glam::ivec2(x, y);
+                if self.load(chunk) {
+                    changes.loaded.push(chunk);
+                }
+            }
+        }
+
+        self.chunks.retain(|chunk, _| {
+            let d = (*chunk - center).abs();
+            let keep = d.x <= unload_radius && d.y <= unload_radius;
+            if !keep {
+                changes.unloaded.push(*chunk);
+            }
+            keep
+        });
+
+        if !changes.unloaded.is_empty() {
+            self.revision += 1;
+        }
+        changes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::maze_generators::RecursiveBacktracker;
+    use crate::maze_paths::DistanceField;
+
+    fn braided_maze() -> ChunkedMaze {
+        ChunkedMaze::new(
+            5,
+            16,
+            Box::new(RecursiveBacktracker),
+            BraidConfig::new(0.5, 0.05),
+            RoomConfig::default(),
+        )
+    }
+
+    #[test]
+    fn chunks_regenerate_the_same() {
+        let mut maze = braided_maze();
+        maze.load(glam::ivec2(1, -1));
+        let grid = maze.chunk(glam::ivec2(1, -1)).unwrap().grid.clone();
+        maze.update(glam::ivec2(1000, 0), 0, 0);
+        assert!(maze.chunk(glam::ivec2(1, -1)).is_none());
+        assert!(maze.load(glam::ivec2(1, -1)));
+        assert_eq!(maze.chunk(glam::ivec2(1, -1)).unwrap().grid, grid);
+    }
+
+    #[test]
+    fn braid_stats_count_each_chunk_once() {
+        let mut maze = braided_maze();
+        maze.update(glam::ivec2(0, 0), 1, 1);
+        let mut expected = BraidStats::default();
+        for (_, chunk) in maze.chunks() {
+            expected.add(chunk.braid_stats);
+        }
+        assert!(expected.loops_added > 0);
+        assert_eq!(maze.braid_stats, expected);
+
+        // walking away and back unloads and regenerates every chunk
+        maze.update(glam::ivec2(1000, 0), 0, 0);
+        expected.add(maze.chunk(glam::ivec2(62, 0)).unwrap().braid_stats);
+        for _ in 0..3 {
+            maze.update(glam::ivec2(0, 0), 1, 1);
+            maze.update(glam::ivec2(1000, 0), 0, 0);
+        }
+        assert_eq!(maze.braid_stats, expected);
+    }
+
+    #[test]
+    fn nothing_past_the_bounds_is_open() {
+        let mut maze = braided_maze();
+        maze.bounds = Some((glam::ivec2(-1, -1), glam::ivec2(1, 1)));
+        maze.update(glam::ivec2(0, 0), 3, 3);
+        let mut endless = braided_maze();
+        endless.load(glam::ivec2(0, 0));
+        assert_eq!(
+            maze.chunk(glam::ivec2(0, 0)).unwrap().grid,
+            endless.chunk(glam::ivec2(0, 0)).unwrap().grid
+        );
+
+        let inside = |tile: glam::IVec2| maze.in_bounds(maze.to_chunk(tile).0);
+        let start = glam::ivec2(1, 1);
+        assert!(maze.get(start).unwrap().is_open());
+        let field = DistanceField::new(&maze, start);
+        assert!(!field.is_empty());
+        assert!(field.iter().all(|(tile, _)| inside(tile)));
+        // the edge chunks' own seam doors are shut too
+        let min = glam::IVec2::splat(-maze.chunk_size);
+        for i in 0..3 * maze.chunk_size {
+            assert!(maze.is_wall(min + glam::ivec2(i, 0)));
+            assert!(maze.is_wall(min + glam::ivec2(0, i)));
+        }
+    }
+}
@@ -0,0 +1,220 @@
+use crate::camera_manager::CameraManager;
+use crate::maze::{self, Tile};
+use crate::maze_atlas;
+use crate::maze_chunks::{self, ChunkedMaze};
+use macroquad::prelude as mq;
+
+const DECOR_SALT: u64 = 0xDEC02;
+
+const CRACK_COLOR: mq::Color = mq::Color::new(0.35, 0.35, 0.38, 1.);
+const MOSS_COLOR: mq::Color = mq::Color::new(0.25, 0.45, 0.2, 1.);
+const BONE_COLOR: mq::Color = mq::Color::new(0.85, 0.82, 0.7, 1.);
+const RUBBLE_COLOR: mq::Color = mq::Color::new(0.5, 0.48, 0.45, 1.);
+
+#[derive(PartialEq, Eq, Debug, Copy, Clone)]
+pub enum Decoration {
+    Crack,
+    Moss,
+    Bones,
+    Rubble,
+}
+impl Decoration {
+    fn choices(tile: Tile) -> &'static [Decoration] {
+        // bones and rubble lie on the ground, walls only crack and grow moss
+        match tile {
+            Tile::Wall => &[Decoration::Crack, Decoration::Moss],
+            Tile::Floor => &[
+                Decoration::Crack,
+                Decoration::Moss,
+                Decoration::Bones,
+                Decoration::Rubble,
+            ],
+            Tile::Room => &[Decoration::Crack, Decoration::Bones, Decoration::Rubble],
+        }
+    }
+}
+
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub struct DecorConfig {
+    // chance each tile of a type gets a decoration, 0 to 1
+    pub wall: f32,
+    pub floor: f32,
+    pub room: f32,
+}
+impl Default for DecorConfig {
+    fn default() -> DecorConfig {
+        DecorConfig::new(0.08, 0.12, 0.2)
+    }
+}
+impl DecorConfig {
+    pub fn new(wall: f32, floor: f32, room: f32) -> DecorConfig {
+        DecorConfig {
+            wall: wall.clamp(0., 1.),
+            floor: floor.clamp(0., 1.),
+            room: room.clamp(0., 1.),
+        }
+    }
+    pub fn parse(s: &str) -> Option<DecorConfig> {
+        // usage: <wall>,<floor>,<room>
+        let densities = s
+            .split(',')
+            .map(|density| density.trim().parse().ok())
+            .collect::<Option<Vec<f32>>>()?;
+        match densities.as_slice() {
+            [wall, floor, room] => Some(DecorConfig::new(*wall, *floor, *room)),
+            _ => None,
+        }
+    }
+    pub fn density(&self, tile: Tile) -> f32 {
+        match tile {
+            Tile::Wall => self.wall,
+            Tile::Floor => self.floor,
+            Tile::Room => self.room,
+        }
+    }
+}
+
+pub fn decoration(
+    seed: u64,
+    tile_pt: mq::IVec2,
+    tile: Tile,
+    config: &DecorConfig,
+) -> Option<(Decoration, u64)> {
+    // rolled from the seed and tile alone, so a reloaded chunk looks the same
+    // return: the decoration and a seed for how it's drawn
+    let tile_seed = maze_chunks::chunk_seed(seed, tile_pt, DECOR_SALT);
+    let rng = maze::create_rng(tile_seed);
+    if rng.gen_range(0., 1.) >= config.density(tile) {
+        return None;
+    }
+    let choices = Decoration::choices(tile);
+    Some((choices[rng.gen_range(0, choices.len())], rng.rand() as u64))
+}
+
+pub fn draw_decoration(decoration: Decoration, draw_seed: u64, pt: mq::Vec2, size: f32) {
+    let rng = maze::create_rng(draw_seed);
+    // a spot somewhere inside the tile, kept off the edges
+    let spot = |margin: f32| {
+        pt + mq::vec2(
+            rng.gen_range(margin, 1. - margin),
+            rng.gen_range(margin, 1. - margin),
+        ) * size
+    };
+    let px = size / 40.;
+    match decoration {
+        Decoration::Crack => {
+            let mut a = spot(0.15);
+            for _ in 0..rng.gen_range(2, 5) {
+                let b = (a + mq::vec2(rng.gen_range(-0.25, 0.25), rng.gen_range(-0.25, 0.25))
+                    * size)
+                    .clamp(pt, pt + size);
+                mq::draw_line(a.x, a.y, b.x, b.y, px, CRACK_COLOR);
+                a = b;
+            }
+        }
+        Decoration::Moss => {
+            let center = spot(0.2);
+            for _ in 0..rng.gen_range(5, 10) {
+                let d = mq::vec2(rng.gen_range(-0.15, 0.15), rng.gen_range(-0.15, 0.15)) * size;
+                mq::draw_rectangle(center.x + d.x, center.y + d.y, 2. * px, 2. * px, MOSS_COLOR);
+            }
+        }
+        Decoration::Bones => {
+            for _ in 0..rng.gen_range(1, 3) {
+                let a = spot(0.25);
+                let angle = rng.gen_range(0., std::f32::consts::TAU);
+                let b = a + mq::Vec2::from_angle(angle) * size * 0.25;
+                mq::draw_line(a.x, a.y, b.x, b.y, px, BONE_COLOR);
+                mq::draw_circle(a.x, a.y, px * 1.5, BONE_COLOR);
+                mq::draw_circle(b.x, b.y, px * 1.5, BONE_COLOR);
+            }
+        }
+        Decoration::Rubble => {
+            let center = spot(0.25);
+            for _ in 0..rng.gen_range(3, 7) {
+                let d = mq::vec2(rng.gen_range(-0.2, 0.2), rng.gen_range(-0.2, 0.2)) * size;
+                let w = rng.gen_range(1.5, 4.) * px;
+                mq::draw_rectangle(center.x + d.x, center.y + d.y, w, w * 0.8, RUBBLE_COLOR);
+            }
+        }
+    }
+}
+
+pub fn draw_decorations(
+    maze: &ChunkedMaze,
+    config: &DecorConfig,
+    cm: &CameraManager,
+    maze_pt: mq::Vec2,
+    view_size: mq::Vec2,
+    tile_size: f32,
+) {
+    // drawn over maze_atlas::draw_maze, unloaded tiles stay bare
+    let (min, max) = maze_atlas::visible_tiles(cm, maze_pt, view_size, tile_size);
+    for y in min.y..=max.y {
+        for x in min.x..=max.x {
+            let tile_pt = mq::ivec2(x, y);
+            let decor = maze
+                .get(tile_pt)
+                .and_then(|tile| decoration(maze.seed, tile_pt, tile, config));
+            if let Some((decor, draw_seed)) = decor {
+                draw_decoration(
+                    decor,
+                    draw_seed,
+                    cm.calc_offset(maze_pt + tile_pt.as_vec2() * tile_size),
+                    tile_size,
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TILES: [Tile; 3] = [Tile::Wall, Tile::Floor, Tile::Room];
+
+    fn tile_pts() -> impl Iterator<Item = mq::IVec2> {
+        (-20..20).flat_map(|y| (-20..20).map(move |x| mq::ivec2(x, y)))
+    }
+
+    #[test]
+    fn the_same_seed_and_tile_decorate_the_same() {
+        let config = DecorConfig::new(0.5, 0.5, 0.5);
+        for tile_pt in tile_pts() {
+            for tile in TILES {
+                assert_eq!(
+                    decoration(3, tile_pt, tile, &config),
+                    decoration(3, tile_pt, tile, &config)
+                );
+            }
+        }
+        // and the seed matters
+        assert!(tile_pts().any(|tile_pt| {
+            decoration(3, tile_pt, Tile::Floor, &config)
+                != decoration(4, tile_pt, Tile::Floor, &config)
+        }));
+    }
+
+    #[test]
+    fn decorations_keep_to_their_tiles() {
+        // walls only, so floor and room tiles stay bare
+        let config = DecorConfig::new(1., 0., 0.);
+        for tile_pt in tile_pts() {
+            assert!(decoration(5, tile_pt, Tile::Wall, &config).is_some());
+            assert_eq!(decoration(5, tile_pt, Tile::Floor, &config), None);
+            assert_eq!(decoration(5, tile_pt, Tile::Room, &config), None);
+        }
+
+        // and nothing lying on the ground ends up on a wall
+        let config = DecorConfig::new(1., 1., 1.);
+        for tile_pt in tile_pts() {
+            for tile in TILES {
+                let (decor, _) = decoration(5, tile_pt, tile, &config).unwrap();
+                assert!(Decoration::choices(tile).contains(&decor));
+            }
+            let (decor, _) = decoration(5, tile_pt, Tile::Wall, &config).unwrap();
+            assert!(!matches!(decor, Decoration::Bones | Decoration::Rubble));
+        }
+    }
+}
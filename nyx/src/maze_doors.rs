@@ -0,0 +1,273 @@
+use crate::maze::{pick_sorted, NEIGHBOR_OFFSETS};
+use crate::maze_paths::{self, DistanceField, Walkable};
+
+use std::collections::{HashMap, HashSet};
+
+#[derive(PartialEq, Eq, Debug, Copy, Clone)]
+pub struct Lock {
+    // the key opens the door with the same index
+    pub door: glam::IVec2,
+    pub key: glam::IVec2,
+    pub key_taken: bool,
+    pub open: bool,
+}
+impl Lock {
+    pub fn new(door: glam::IVec2, key: glam::IVec2) -> Lock {
+        Lock {
+            door,
+            key,
+            key_taken: false,
+            open: false,
+        }
+    }
+}
+
+pub struct Locked<'a, W: Walkable + ?Sized> {
+    // a maze with some tiles shut
+    pub maze: &'a W,
+    pub doors: &'a [glam::IVec2],
+}
+impl<W: Walkable + ?Sized> Walkable for Locked<'_, W> {
+    fn is_open(&self, tile: glam::IVec2) -> bool {
+        self.maze.is_open(tile) && !self.doors.contains(&tile)
+    }
+    fn revision(&self) -> u64 {
+        self.maze.revision()
+    }
+}
+
+fn reachable<W: Walkable + ?Sized>(
+    maze: &W,
+    start: glam::IVec2,
+    doors: &[glam::IVec2],
+) -> HashSet<glam::IVec2> {
+    DistanceField::new(&Locked { maze, doors }, start)
+        .iter()
+        .map(|(tile, _)| tile)
+        .collect()
+}
+
+fn cut_tiles<W: Walkable + ?Sized>(maze: &W, path: &[glam::IVec2]) -> Vec<bool> {
+    // return: for each path tile, whether shutting it alone cuts the ends of path apart
+    // anything touching the path at two spots is a way around the tiles between them
+    let index = path
+        .iter()
+        .enumerate()
+        .map(|(i, tile)| (*tile, i))
+        .collect::<HashMap<glam::IVec2, usize>>();
+    let mut bypassed = vec![false; path.len()];
+    let mut bypass = |a: usize, b: usize| {
+        for skipped in bypassed.iter_mut().take(b).skip(a + 1) {
+            *skipped = true;
+        }
+    };
+    let mut seen = HashSet::new();
+    for (i, tile) in path.iter().enumerate() {
+        for offset in NEIGHBOR_OFFSETS.iter() {
+            let side = *tile + *offset;
+            if let Some(j) = index.get(&side) {
+                if *j > i + 1 {
+                    bypass(i, *j);
+                }
+                continue;
+            }
+            if !maze.is_open(side) || !seen.insert(side) {
+                continue;
+            }
+            // flood the pocket off the path and note every path tile it touches
+            let (mut first, mut last) = (i, i);
+            let mut stack = vec![side];
+            while let Some(tile) = stack.pop() {
+                for offset in NEIGHBOR_OFFSETS.iter() {
+                    let new_tile = tile + *offset;
+                    if let Some(j) = index.get(&new_tile) {
+                        first = first.min(*j);
+                        last = last.max(*j);
+                    } else if maze.is_open(new_tile) && seen.insert(new_tile) {
+                        stack.push(new_tile);
+                    }
+                }
+            }
+            bypass(first, last);
+        }
+    }
+    bypassed
+        .iter()
+        .enumerate()
+        .map(|(i, bypassed)| !bypassed && i > 0 && i < path.len() - 1)
+        .collect()
+}
+
+pub fn place_locks<W: Walkable + ?Sized>(
+    maze: &W,
+    start: glam::IVec2,
+    exit: glam::IVec2,
+    count: usize,
+    rng: &quad_rand::RandGenerator,
+) -> Vec<Lock> {
+    // doors go along the way to the exit, each one has to cut the exit off on its own
+    // unloaded tiles count as walls, so the whole maze has to be loaded, see ChunkedMaze::bounds
+    // return: fewer than count locks when the way to the exit is too short or too loopy
+    let path = match maze_paths::find_path(maze, start, exit) {
+        Some(path) => path,
+        None => return vec![],
+    };
+    let cut = cut_tiles(maze, &path);
+    let mut doors: Vec<glam::IVec2> = vec![];
+    let mut last = 0;
+    for i in 0..count {
+        // spread out along the path, with a tile between doors for the key to sit on
+        let target = path.len() * (i + 1) / (count + 1);
+        let door = (last + 2..path.len() - 1)
+            .filter(|idx| cut[*idx])
+            .min_by_key(|idx| idx.abs_diff(target));
+        match door {
+            Some(idx) => {
+                doors.push(path[idx]);
+                last = idx;
+            }
+            None => break,
+        }
+    }
+
+    // each key goes in the part of the maze the door before it opens up
+    let mut seen = HashSet::new();
+    let mut locks = vec![];
+    for (i, door) in doors.iter().enumerate() {
+        let region = reachable(maze, start, &doors[i..]);
+        let tiles = region
+            .iter()
+            .filter(|tile| !seen.contains(*tile) && **tile != start && !doors.contains(tile))
+            .copied()
+            .collect::<Vec<glam::IVec2>>();
+        seen.extend(region);
+        // dead ends make the player go looking
+        let dead_ends = tiles
+            .iter()
+            .filter(|tile| {
+                NEIGHBOR_OFFSETS
+                    .iter()
+                    .filter(|offset| maze.is_open(**tile + **offset))
+                    .count()
+                    == 1
+            })
+            .copied()
+            .collect::<Vec<glam::IVec2>>();
+        let key = if dead_ends.is_empty() {
+            pick_sorted(tiles, rng)
+        } else {
+            pick_sorted(dead_ends, rng)
+        };
+        match key {
+            Some(key) => locks.push(Lock::new(*door, key)),
+            None => break,
+        }
+    }
+    locks
+}
+
+pub fn route_length<W: Walkable + ?Sized>(
+    maze: &W,
+    start: glam::IVec2,
+    exit: glam::IVec2,
+    locks: &[Lock],
+) -> Option<u32> {
+    // shortest walk from start through every key in order, then to the exit
+    let doors = locks
+        .iter()
+        .map(|lock| lock.door)
+        .collect::<Vec<glam::IVec2>>();
+    let stops = locks
+        .iter()
+        .map(|lock| lock.key)
+        .chain(std::iter::once(exit))
+        .collect::<Vec<glam::IVec2>>();
+    let mut from = start;
+    let mut length = 0;
+    for (i, stop) in stops.iter().enumerate() {
+        // doors before key i are open by the time the player heads for it
+        let locked = Locked {
+            maze,
+            doors: &doors[i..],
+        };
+        length += DistanceField::new(&locked, from).get(*stop)?;
+        from = *stop;
+    }
+    Some(length)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::maze_braid::BraidConfig;
+    use crate::maze_chunks::ChunkedMaze;
+    use crate::maze_generators;
+    use crate::maze_io;
+    use crate::maze_rooms::RoomConfig;
+
+    // a corridor, a loop around the middle wall, and another corridor
+    const MAZE: &str = "\
+###########
+#####...###
+#.....#...#
+#####...###
+###########
+";
+
+    #[test]
+    fn cut_tiles_are_the_corridors() {
+        let grid = maze_io::from_ascii(MAZE).unwrap();
+        let (from, to) = (glam::ivec2(1, 2), glam::ivec2(9, 2));
+        let path = maze_paths::find_path(&grid, from, to).unwrap();
+        let cuts = path
+            .iter()
+            .zip(cut_tiles(&grid, &path))
+            .filter(|(_, cut)| *cut)
+            .map(|(tile, _)| *tile)
+            .collect::<Vec<glam::IVec2>>();
+        let corridors = [2, 3, 4, 5, 7, 8].map(|x| glam::ivec2(x, 2));
+        assert_eq!(cuts, corridors);
+        for tile in &path[1..path.len() - 1] {
+            let doors = [*tile];
+            let locked = Locked {
+                maze: &grid,
+                doors: &doors,
+            };
+            let cut_off = maze_paths::find_path(&locked, from, to).is_none();
+            assert_eq!(cut_off, corridors.contains(tile));
+        }
+    }
+
+    #[test]
+    fn keys_come_before_their_doors() {
+        let mut maze = ChunkedMaze::new(
+            11,
+            16,
+            maze_generators::parse_generator("backtracker").unwrap(),
+            BraidConfig::new(0.3, 0.),
+            RoomConfig::default(),
+        );
+        maze.bounds = Some((glam::ivec2(-1, -1), glam::ivec2(1, 1)));
+        maze.update(glam::ivec2(0, 0), 1, 1);
+        let (start, exit) = (glam::ivec2(1, 1), glam::ivec2(31, 31));
+        let locks = place_locks(&maze, start, exit, 3, &crate::maze::create_rng(11));
+        assert_eq!(locks.len(), 3);
+
+        // the chunks past the bounds load later, they can't lead around a door
+        maze.update(glam::ivec2(0, 0), 3, 3);
+        let doors = locks
+            .iter()
+            .map(|lock| lock.door)
+            .collect::<Vec<glam::IVec2>>();
+        for (i, lock) in locks.iter().enumerate() {
+            // reachable with only the doors before it open, and no sooner
+            assert!(reachable(&maze, start, &doors[i..]).contains(&lock.key));
+            assert!(!reachable(&maze, start, &doors[i..i + 1]).contains(&exit));
+            if i > 0 {
+                assert!(!reachable(&maze, start, &doors[i - 1..]).contains(&lock.key));
+            }
+        }
+        assert!(reachable(&maze, start, &[]).contains(&exit));
+        assert!(route_length(&maze, start, exit, &locks).is_some());
+    }
+}
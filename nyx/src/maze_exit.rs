@@ -0,0 +1,119 @@
+use crate::maze::pick_sorted;
+use crate::maze_paths::{DistanceField, Walkable};
+
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Default)]
+pub enum ExitPlacement {
+    #[default]
+    Farthest, // the reachable tile with the longest path from the start
+    RandomEdge, // a reachable tile on the edge of the reachable area, never the start
+    Fixed(glam::IVec2),
+}
+impl ExitPlacement {
+    pub fn parse(s: &str) -> Option<ExitPlacement> {
+        // usage: farthest | edge | <x>,<y>
+        match s {
+            "farthest" => Some(ExitPlacement::Farthest),
+            "edge" => Some(ExitPlacement::RandomEdge),
+            _ => {
+                let (x, y) = s.split_once(',')?;
+                Some(ExitPlacement::Fixed(glam::ivec2(
+                    x.trim().parse().ok()?,
+                    y.trim().parse().ok()?,
+                )))
+            }
+        }
+    }
+}
+
+pub fn place_exit<W: Walkable + ?Sized>(
+    maze: &W,
+    start: glam::IVec2,
+    placement: ExitPlacement,
+    rng: &quad_rand::RandGenerator,
+) -> Option<glam::IVec2> {
+    // return: None when the exit can't be reached from start
+    let field = DistanceField::new(maze, start);
+    match placement {
+        ExitPlacement::Farthest => field.farthest().map(|(tile, _)| tile),
+        ExitPlacement::RandomEdge => {
+            let tiles = field
+                .iter()
+                .map(|(tile, _)| tile)
+                .collect::<Vec<glam::IVec2>>();
+            let min = tiles
+                .iter()
+                .fold(glam::IVec2::splat(i32::MAX), |a, b| a.min(*b));
+            let max = tiles
+                .iter()
+                .fold(glam::IVec2::splat(i32::MIN), |a, b| a.max(*b));
+            let edge = tiles
+                .into_iter()
+                .filter(|tile| {
+                    tile.x == min.x || tile.y == min.y || tile.x == max.x || tile.y == max.y
+                })
+                .filter(|tile| *tile != start);
+            // the start is the whole edge, a corridor running away from it still has an end
+            pick_sorted(edge, rng).or_else(|| field.farthest().map(|(tile, _)| tile))
+        }
+        ExitPlacement::Fixed(tile) => field.get(tile).map(|_| tile),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{maze, maze_generators, maze_io};
+
+    // the start is in the corner, so it's on the edge of what it reaches
+    const MAZE: &str = "\
+#########
+#.....#.#
+#.###.#.#
+#.#...#.#
+#.#.###.#
+#...#...#
+#########
+";
+
+    #[test]
+    fn farthest_is_the_farthest() {
+        let generator = maze_generators::parse_generator("kruskal").unwrap();
+        let grid = maze::create_maze_map(21., glam::vec2(11., 11.), 8, generator.as_ref());
+        let start = glam::ivec2(11, 11);
+        let exit = place_exit(&grid, start, ExitPlacement::Farthest, &maze::create_rng(8));
+        let field = DistanceField::new(&grid, start);
+        let d = field.get(exit.unwrap()).unwrap();
+        assert!(field.iter().all(|(_, other_d)| other_d <= d));
+    }
+
+    #[test]
+    fn edge_is_never_the_start() {
+        let grid = maze_io::from_ascii(MAZE).unwrap();
+        let start = glam::ivec2(1, 1);
+        let field = DistanceField::new(&grid, start);
+        for seed in 0..50 {
+            let rng = maze::create_rng(seed);
+            let exit = place_exit(&grid, start, ExitPlacement::RandomEdge, &rng).unwrap();
+            assert_ne!(exit, start);
+            assert!(field.get(exit).is_some());
+        }
+
+        // a dead end where only the start is on the edge falls back to the far end
+        let grid = maze_io::from_ascii("###\n#.#\n#.#\n#.#\n###\n").unwrap();
+        let rng = maze::create_rng(0);
+        let exit = place_exit(&grid, glam::ivec2(1, 2), ExitPlacement::RandomEdge, &rng);
+        assert!(exit.is_some_and(|exit| exit != glam::ivec2(1, 2)));
+    }
+
+    #[test]
+    fn fixed_has_to_be_reachable() {
+        let grid = maze_io::from_ascii(MAZE).unwrap();
+        let start = glam::ivec2(1, 1);
+        let rng = maze::create_rng(0);
+        let fixed = |x, y| place_exit(&grid, start, ExitPlacement::Fixed(glam::ivec2(x, y)), &rng);
+        assert_eq!(fixed(3, 5), Some(glam::ivec2(3, 5)));
+        // a wall, and floor the start can't get to
+        assert_eq!(fixed(2, 2), None);
+        assert_eq!(fixed(7, 1), None);
+    }
+}
@@ -0,0 +1,205 @@
+use crate::maze;
+use crate::maze_chunks::{self, ChunkChanges, ChunkedMaze};
+
+use std::collections::{hash_map::Entry, HashMap, VecDeque};
+
+const FLOOR_SALT: u64 = 0xF1002;
+const STAIRS_SALT: u64 = 0x57A125;
+
+pub fn floor_seed(seed: u64, floor: usize) -> u64 {
+    // the top floor keeps the world seed so one floor mazes don't change
+    if floor == 0 {
+        seed
+    } else {
+        maze_chunks::chunk_seed(seed, glam::ivec2(floor as i32, 0), FLOOR_SALT)
+    }
+}
+
+pub struct MazeFloors {
+    pub floors: Vec<ChunkedMaze>, // 0 is the top, stairs go down to the next one
+}
+impl MazeFloors {
+    pub fn new(floors: Vec<ChunkedMaze>) -> MazeFloors {
+        MazeFloors { floors }
+    }
+    pub fn len(&self) -> usize {
+        self.floors.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.floors.is_empty()
+    }
+    pub fn floor(&self, floor: usize) -> &ChunkedMaze {
+        &self.floors[floor]
+    }
+    pub fn stairs_down(&self, floor: usize, chunk: glam::IVec2) -> Option<glam::IVec2> {
+        // one staircase per chunk, on a cell so it's open on both floors it joins
+        if floor + 1 >= self.len() {
+            return None;
+        }
+        let maze_map = self.floor(floor);
+        let layout = maze_chunks::chunk_layout(maze_map.chunk_size);
+        let rng = maze::create_rng(maze_chunks::chunk_seed(maze_map.seed, chunk, STAIRS_SALT));
+        let cell = layout.cell_at(rng.gen_range(0, layout.len()));
+        let tile = chunk * maze_map.chunk_size + layout.to_tile(cell);
+        // the way back up wins if both land on the same cell
+        if floor > 0 && self.stairs_down(floor - 1, chunk) == Some(tile) {
+            None
+        } else {
+            Some(tile)
+        }
+    }
+    pub fn stairs_at(&self, floor: usize, tile: glam::IVec2) -> Option<usize> {
+        // return: the floor the stairs on tile lead to
+        let (chunk, _) = self.floor(floor).to_chunk(tile);
+        if floor > 0 && self.stairs_down(floor - 1, chunk) == Some(tile) {
+            Some(floor - 1)
+        } else if self.stairs_down(floor, chunk) == Some(tile) {
+            Some(floor + 1)
+        } else {
+            None
+        }
+    }
+    pub fn stairs(&self, floor: usize) -> Vec<(glam::IVec2, usize)> {
+        // every loaded staircase on floor, with the floor it leads to
+        self.floor(floor)
+            .chunks()
+            .flat_map(|(chunk, _)| {
+                let up = (floor > 0)
+                    .then(|| self.stairs_down(floor - 1, *chunk))
+                    .flatten()
+                    .map(|tile| (tile, floor - 1));
+                let down = self
+                    .stairs_down(floor, *chunk)
+                    .map(|tile| (tile, floor + 1));
+                up.into_iter().chain(down)
+            })
+            .collect()
+    }
+    pub fn update(
+        &mut self,
+        tile: glam::IVec2,
+        load_radius: i32,
+        unload_radius: i32,
+    ) -> Vec<ChunkChanges> {
+        // every floor keeps the same chunks loaded so stairs always land somewhere
+        self.floors
+            .iter_mut()
+            .map(|maze_map| maze_map.update(tile, load_radius, unload_radius))
+            .collect()
+    }
+    pub fn distance(&self, from: (usize, glam::IVec2), to: (usize, glam::IVec2)) -> Option<u32> {
+        // breadth first over loaded tiles on every floor, taking stairs costs a step
+        let mut distances: HashMap<(usize, glam::IVec2), u32> = HashMap::new();
+        let mut queue = VecDeque::new();
+        if self
+            .floor(from.0)
+            .get(from.1)
+            .is_some_and(|tile| tile.is_open())
+        {
+            distances.insert(from, 0);
+            queue.push_back(from);
+        }
+        while let Some((floor, tile)) = queue.pop_front() {
+            let d = distances[&(floor, tile)];
+            if (floor, tile) == to {
+                return Some(d);
+            }
+            let mut next = maze::NEIGHBOR_OFFSETS
+                .iter()
+                .map(|offset| (floor, tile + *offset))
+                .filter(|(floor, tile)| {
+                    self.floor(*floor)
+                        .get(*tile)
+                        .is_some_and(|tile| tile.is_open())
+                })
+                .collect::<Vec<(usize, glam::IVec2)>>();
+            if let Some(new_floor) = self.stairs_at(floor, tile) {
+                next.push((new_floor, tile));
+            }
+            for step in next {
+                if let Entry::Vacant(entry) = distances.entry(step) {
+                    entry.insert(d + 1);
+                    queue.push_back(step);
+                }
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::maze_braid::BraidConfig;
+    use crate::maze_generators;
+    use crate::maze_paths::DistanceField;
+    use crate::maze_rooms::RoomConfig;
+
+    const CHUNK_SIZE: i32 = 16;
+    const START: glam::IVec2 = glam::ivec2(9, 9);
+
+    fn floors(seed: u64) -> MazeFloors {
+        let mut floors = MazeFloors::new(
+            (0..3)
+                .map(|floor| {
+                    ChunkedMaze::new(
+                        floor_seed(seed, floor),
+                        CHUNK_SIZE,
+                        maze_generators::parse_generator("backtracker").unwrap(),
+                        BraidConfig::default(),
+                        RoomConfig::default(),
+                    )
+                })
+                .collect(),
+        );
+        floors.update(START, 1, 2);
+        floors
+    }
+
+    fn sorted_stairs(floors: &MazeFloors, floor: usize) -> Vec<(glam::IVec2, usize)> {
+        let mut stairs = floors.stairs(floor);
+        stairs.sort_by_key(|(tile, to)| (tile.y, tile.x, *to));
+        stairs
+    }
+
+    #[test]
+    fn stairs_down_are_reachable_from_the_way_in() {
+        for seed in 0..5 {
+            let floors = floors(seed);
+            let mut arrivals = vec![START];
+            for floor in 0..floors.len() {
+                let downs = floors
+                    .floor(floor)
+                    .chunks()
+                    .filter_map(|(chunk, _)| floors.stairs_down(floor, *chunk))
+                    .collect::<Vec<glam::IVec2>>();
+                assert_eq!(downs.is_empty(), floor == floors.len() - 1);
+                for arrival in arrivals.iter() {
+                    let field = DistanceField::new(floors.floor(floor), *arrival);
+                    for down in downs.iter() {
+                        assert!(field.get(*down).is_some(), "seed {} floor {}", seed, floor);
+                        assert_eq!(floors.stairs_at(floor, *down), Some(floor + 1));
+                        assert_eq!(floors.stairs_at(floor + 1, *down), Some(floor));
+                        assert!(floors.floor(floor + 1).get(*down).unwrap().is_open());
+                    }
+                }
+                arrivals = downs;
+            }
+        }
+    }
+
+    #[test]
+    fn the_same_seed_makes_the_same_floors() {
+        assert_eq!(floor_seed(7, 0), 7);
+        assert_ne!(floor_seed(7, 1), floor_seed(7, 2));
+        let (a, b) = (floors(7), floors(7));
+        for floor in 0..a.len() {
+            for (chunk, loaded) in a.floor(floor).chunks() {
+                assert_eq!(loaded.grid, b.floor(floor).chunk(*chunk).unwrap().grid);
+            }
+            assert_eq!(sorted_stairs(&a, floor), sorted_stairs(&b, floor));
+        }
+        // and another seed doesn't
+        assert_ne!(sorted_stairs(&a, 0), sorted_stairs(&floors(8), 0));
+    }
+}
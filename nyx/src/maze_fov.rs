@@ -0,0 +1,183 @@
+use crate::maze_paths::Walkable;
+
+use std::collections::HashSet;
+
+// symmetric shadowcasting, scanned a row at a time out from the origin in each quadrant
+// if a can see b then b can see a, and walls are seen but nothing past them
+
+struct Row {
+    depth: i32,
+    start_slope: f32,
+    end_slope: f32,
+}
+impl Row {
+    fn cols(&self) -> std::ops::RangeInclusive<i32> {
+        // rounds toward the middle of a tile when a slope lands right on its edge
+        let min = (self.depth as f32 * self.start_slope + 0.5).floor() as i32;
+        let max = (self.depth as f32 * self.end_slope - 0.5).ceil() as i32;
+        min..=max
+    }
+    fn next(&self) -> Row {
+        Row {
+            depth: self.depth + 1,
+            ..*self
+        }
+    }
+    fn is_symmetric(&self, col: i32) -> bool {
+        let col = col as f32;
+        col >= self.depth as f32 * self.start_slope && col <= self.depth as f32 * self.end_slope
+    }
+}
+
+fn slope(depth: i32, col: i32) -> f32 {
+    // the edge of the tile nearest the start of the row
+    (2 * col - 1) as f32 / (2 * depth) as f32
+}
+
+pub fn field_of_view<W: Walkable + ?Sized>(
+    maze: &W,
+    origin: glam::IVec2,
+    radius: i32,
+) -> HashSet<glam::IVec2> {
+    // return: every tile with a clear line from the middle of origin, up to radius tiles out
+    // along either axis
+    let mut seen = HashSet::from([origin]);
+    let quadrants: [fn(i32, i32) -> glam::IVec2; 4] = [
+        |depth: i32, col: i32| glam::ivec2(col, -depth),
+        |depth: i32, col: i32| glam::ivec2(depth, col),
+        |depth: i32, col: i32| glam::ivec2(col, depth),
+        |depth: i32, col: i32| glam::ivec2(-depth, col),
+    ];
+    for to_offset in quadrants {
+        let tile_at = |depth: i32, col: i32| origin + to_offset(depth, col);
+        let mut rows = vec![Row {
+            depth: 1,
+            start_slope: -1.,
+            end_slope: 1.,
+        }];
+        while let Some(mut row) = rows.pop() {
+            if row.depth > radius {
+                continue;
+            }
+            let mut last_open = None;
+            for col in row.cols() {
+                let open = maze.is_open(tile_at(row.depth, col));
+                if !open || row.is_symmetric(col) {
+                    seen.insert(tile_at(row.depth, col));
+                }
+                match (last_open, open) {
+                    // a wall ended, the rest of the row starts past its edge
+                    (Some(false), true) => row.start_slope = slope(row.depth, col),
+                    // a wall started, light through the gap before it goes on a row deeper
+                    (Some(true), false) => rows.push(Row {
+                        end_slope: slope(row.depth, col),
+                        ..row.next()
+                    }),
+                    _ => (),
+                }
+                last_open = Some(open);
+            }
+            if last_open == Some(true) {
+                rows.push(row.next());
+            }
+        }
+    }
+    seen
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::maze_doors::Locked;
+    use crate::{maze, maze_generators, maze_io};
+
+    use std::collections::HashMap;
+
+    // a room with pillars to see around
+    const ROOM: &str = "\
+###########
+#_________#
+#_#___#___#
+#_____#_#_#
+#__#______#
+#_________#
+###########
+";
+
+    const CORRIDOR: &str = "\
+#########
+#.......#
+#########
+";
+
+    fn assert_symmetric(maze: &maze::MazeGrid, radius: i32) {
+        let open = maze
+            .iter()
+            .filter(|(_, tile)| tile.is_open())
+            .map(|(tile, _)| tile)
+            .collect::<Vec<glam::IVec2>>();
+        let views = open
+            .iter()
+            .map(|tile| (*tile, field_of_view(maze, *tile, radius)))
+            .collect::<HashMap<_, _>>();
+        for a in open.iter() {
+            for b in open.iter() {
+                assert_eq!(
+                    views[a].contains(b),
+                    views[b].contains(a),
+                    "{} and {} don't agree",
+                    a,
+                    b
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn seeing_goes_both_ways() {
+        assert_symmetric(&maze_io::from_ascii(ROOM).unwrap(), 12);
+        let generator = maze_generators::parse_generator("prim").unwrap();
+        let grid = maze::create_maze_map(15., glam::vec2(7., 7.), 3, generator.as_ref());
+        assert_symmetric(&grid, 8);
+    }
+
+    #[test]
+    fn walls_are_seen_but_not_past() {
+        let mut grid = maze_io::from_ascii(CORRIDOR).unwrap();
+        grid.set(glam::ivec2(4, 1), maze::Tile::Wall);
+        let seen = field_of_view(&grid, glam::ivec2(1, 1), 10);
+        assert!(seen.contains(&glam::ivec2(3, 1)));
+        assert!(seen.contains(&glam::ivec2(4, 1)));
+        assert!(!seen.contains(&glam::ivec2(5, 1)));
+        assert!(!seen.contains(&glam::ivec2(7, 1)));
+    }
+
+    #[test]
+    fn closed_doors_block_the_view() {
+        let grid = maze_io::from_ascii(CORRIDOR).unwrap();
+        let origin = glam::ivec2(1, 1);
+        let door = [glam::ivec2(4, 1)];
+
+        let closed = field_of_view(
+            &Locked {
+                maze: &grid,
+                doors: &door,
+            },
+            origin,
+            10,
+        );
+        assert!(closed.contains(&door[0]));
+        assert!(!closed.contains(&glam::ivec2(5, 1)));
+
+        let open = field_of_view(
+            &Locked {
+                maze: &grid,
+                doors: &[],
+            },
+            origin,
+            10,
+        );
+        assert!(open.contains(&door[0]));
+        assert!(open.contains(&glam::ivec2(7, 1)));
+    }
+}
@@ -0,0 +1,382 @@
+use crate::maze::{self, CellLayout, MazeGrid};
+use crate::maze_topology::{SquareTopology, Topology};
+
+use std::collections::HashMap;
+
+pub trait MazeGenerator {
+    fn name(&self) -> String;
+    // carves passages between the cells of layout, starting from the start cell
+    fn generate(
+        &self,
+        grid: &mut MazeGrid,
+        layout: &CellLayout,
+        start: glam::IVec2,
+        rng: &quad_rand::RandGenerator,
+    );
+}
+
+pub fn parse_generator(name: &str) -> Option<Box<dyn MazeGenerator>> {
+    // usage: backtracker | prim | kruskal | wilson | eller | growing-tree[:<selection>]
+    let (algorithm, options) = match name.split_once(':') {
+        Some((algorithm, options)) => (algorithm, Some(options)),
+        None => (name, None),
+    };
+    match (algorithm, options) {
+        ("backtracker", None) => Some(Box::new(RecursiveBacktracker)),
+        ("prim", None) => Some(Box::new(Prim)),
+        ("kruskal", None) => Some(Box::new(Kruskal)),
+        ("wilson", None) => Some(Box::new(Wilson)),
+        ("eller", None) => Some(Box::new(Eller)),
+        ("growing-tree", None) => Some(Box::new(GrowingTree::new(CellSelection::Newest))),
+        ("growing-tree", Some(selection)) => {
+            CellSelection::parse(selection).map(|s| Box::new(GrowingTree::new(s)) as _)
+        }
+        _ => None,
+    }
+}
+
+fn random_item<T: Copy>(items: &[T], rng: &quad_rand::RandGenerator) -> T {
+    items[rng.gen_range(0, items.len())]
+}
+
+fn uncarved_neighbors(grid: &MazeGrid, layout: &CellLayout, cell: glam::IVec2) -> Vec<glam::IVec2> {
+    layout
+        .neighbors(cell)
+        .filter(|new_cell| !layout.is_carved(grid, *new_cell))
+        .collect()
+}
+
+pub fn backtrack(topology: &mut dyn Topology, start: usize, rng: &quad_rand::RandGenerator) {
+    // only needs neighbors, so it runs on any topology
+    let mut stack: Vec<usize> = vec![start];
+    topology.carve_cell(start);
+
+    while let Some(current_cell) = stack.pop() {
+        let offset_locs = topology
+            .neighbors(current_cell)
+            .into_iter()
+            .filter(|new_cell| !topology.is_carved(*new_cell))
+            .collect::<Vec<usize>>();
+        if !offset_locs.is_empty() {
+            stack.push(current_cell);
+            let new_cell = random_item(&offset_locs, rng);
+            topology.carve_passage(current_cell, new_cell);
+            stack.push(new_cell);
+        }
+    }
+}
+
+pub struct RecursiveBacktracker;
+impl MazeGenerator for RecursiveBacktracker {
+    fn name(&self) -> String {
+        "backtracker".to_owned()
+    }
+    fn generate(
+        &self,
+        grid: &mut MazeGrid,
+        layout: &CellLayout,
+        start: glam::IVec2,
+        rng: &quad_rand::RandGenerator,
+    ) {
+        backtrack(&mut SquareTopology { grid, layout }, layout.idx(start), rng);
+    }
+}
+
+pub struct Prim;
+impl MazeGenerator for Prim {
+    fn name(&self) -> String {
+        "prim".to_owned()
+    }
+    fn generate(
+        &self,
+        grid: &mut MazeGrid,
+        layout: &CellLayout,
+        start: glam::IVec2,
+        rng: &quad_rand::RandGenerator,
+    ) {
+        let mut in_frontier = vec![false; layout.len()];
+        let mut frontier: Vec<glam::IVec2> = vec![];
+        let mut add_frontier = |grid: &MazeGrid, frontier: &mut Vec<glam::IVec2>, cell| {
+            for new_cell in uncarved_neighbors(grid, layout, cell) {
+                if !in_frontier[layout.idx(new_cell)] {
+                    in_frontier[layout.idx(new_cell)] = true;
+                    frontier.push(new_cell);
+                }
+            }
+        };
+
+        layout.carve_cell(grid, start);
+        add_frontier(grid, &mut frontier, start);
+
+        while !frontier.is_empty() {
+            let cell = frontier.swap_remove(rng.gen_range(0, frontier.len()));
+            let carved = layout
+                .neighbors(cell)
+                .filter(|new_cell| layout.is_carved(grid, *new_cell))
+                .collect::<Vec<glam::IVec2>>();
+            layout.carve_passage(grid, random_item(&carved, rng), cell);
+            add_frontier(grid, &mut frontier, cell);
+        }
+    }
+}
+
+pub struct Kruskal;
+impl MazeGenerator for Kruskal {
+    fn name(&self) -> String {
+        "kruskal".to_owned()
+    }
+    fn generate(
+        &self,
+        grid: &mut MazeGrid,
+        layout: &CellLayout,
+        start: glam::IVec2,
+        rng: &quad_rand::RandGenerator,
+    ) {
+        fn find(sets: &mut [usize], mut idx: usize) -> usize {
+            while sets[idx] != idx {
+                sets[idx] = sets[sets[idx]];
+                idx = sets[idx];
+            }
+            idx
+        }
+
+        let mut sets: Vec<usize> = (0..layout.len()).collect();
+        let mut edges: Vec<(glam::IVec2, glam::IVec2)> = vec![];
+        for cell in layout.cells() {
+            for offset in [glam::ivec2(1, 0), glam::ivec2(0, 1)] {
+                if layout.contains(cell + offset) {
+                    edges.push((cell, cell + offset));
+                }
+            }
+        }
+        maze::shuffle(&mut edges, rng);
+
+        layout.carve_cell(grid, start);
+        for (a, b) in edges {
+            let a_set = find(&mut sets, layout.idx(a));
+            let b_set = find(&mut sets, layout.idx(b));
+            if a_set != b_set {
+                sets[a_set] = b_set;
+                layout.carve_passage(grid, a, b);
+            }
+        }
+    }
+}
+
+pub struct Wilson;
+impl MazeGenerator for Wilson {
+    fn name(&self) -> String {
+        "wilson".to_owned()
+    }
+    fn generate(
+        &self,
+        grid: &mut MazeGrid,
+        layout: &CellLayout,
+        start: glam::IVec2,
+        rng: &quad_rand::RandGenerator,
+    ) {
+        let mut in_maze = vec![false; layout.len()];
+        // the last direction the random walk left each cell in, which erases loops for free
+        let mut walk_next = vec![0; layout.len()];
+
+        in_maze[layout.idx(start)] = true;
+        layout.carve_cell(grid, start);
+
+        let mut order: Vec<usize> = (0..layout.len()).collect();
+        maze::shuffle(&mut order, rng);
+
+        for idx in order
+            .into_iter()
+            .filter(|idx| layout.contains(layout.cell_at(*idx)))
+        {
+            let mut cell = layout.cell_at(idx);
+            while !in_maze[layout.idx(cell)] {
+                let neighbors = layout.neighbors(cell).collect::<Vec<glam::IVec2>>();
+                let new_cell = random_item(&neighbors, rng);
+                walk_next[layout.idx(cell)] = layout.idx(new_cell);
+                cell = new_cell;
+            }
+
+            let mut cell = layout.cell_at(idx);
+            while !in_maze[layout.idx(cell)] {
+                in_maze[layout.idx(cell)] = true;
+                let new_cell = layout.cell_at(walk_next[layout.idx(cell)]);
+                layout.carve_passage(grid, cell, new_cell);
+                cell = new_cell;
+            }
+        }
+    }
+}
+
+pub struct Eller;
+impl MazeGenerator for Eller {
+    fn name(&self) -> String {
+        "eller".to_owned()
+    }
+    fn generate(
+        &self,
+        grid: &mut MazeGrid,
+        layout: &CellLayout,
+        _start: glam::IVec2,
+        rng: &quad_rand::RandGenerator,
+    ) {
+        if layout.is_empty() {
+            return;
+        }
+
+        // 0 means the cell has no set yet
+        let mut row_sets = vec![0; layout.cols as usize];
+        let mut next_set = 1;
+
+        for y in 0..layout.rows {
+            let last_row = y == layout.rows - 1;
+
+            for (x, set) in row_sets.iter_mut().enumerate() {
+                if !layout.contains(glam::ivec2(x as i32, y)) {
+                    *set = 0; // masked out cells belong to no set
+                    continue;
+                }
+                if *set == 0 {
+                    *set = next_set;
+                    next_set += 1;
+                }
+                layout.carve_cell(grid, glam::ivec2(x as i32, y));
+            }
+
+            for x in 0..layout.cols as usize - 1 {
+                let both = row_sets[x] != 0 && row_sets[x + 1] != 0;
+                if both && row_sets[x] != row_sets[x + 1] && (last_row || rng.gen_range(0, 2) == 0)
+                {
+                    let (old_set, new_set) = (row_sets[x + 1], row_sets[x]);
+                    for set in row_sets.iter_mut().filter(|set| **set == old_set) {
+                        *set = new_set;
+                    }
+                    layout.carve_passage(
+                        grid,
+                        glam::ivec2(x as i32, y),
+                        glam::ivec2(x as i32 + 1, y),
+                    );
+                }
+            }
+
+            if last_row {
+                break;
+            }
+
+            // every set needs at least one passage down or it gets cut off, a mask can still cut
+            // one off, which connect_regions patches up
+            let goes_down =
+                |x: usize| row_sets[x] != 0 && layout.contains(glam::ivec2(x as i32, y + 1));
+            let mut remaining: HashMap<usize, usize> = HashMap::new();
+            for x in (0..layout.cols as usize).filter(|x| goes_down(*x)) {
+                *remaining.entry(row_sets[x]).or_insert(0) += 1;
+            }
+            let mut has_down: HashMap<usize, bool> = HashMap::new();
+            let mut xs: Vec<usize> = (0..layout.cols as usize).collect();
+            maze::shuffle(&mut xs, rng);
+
+            let mut next_sets = vec![0; layout.cols as usize];
+            for x in xs.into_iter().filter(|x| goes_down(*x)) {
+                let set = row_sets[x];
+                let left = remaining.get_mut(&set).unwrap();
+                *left -= 1;
+                let down = has_down.entry(set).or_insert(false);
+                if (*left == 0 && !*down) || rng.gen_range(0, 2) == 0 {
+                    *down = true;
+                    next_sets[x] = set;
+                    layout.carve_passage(
+                        grid,
+                        glam::ivec2(x as i32, y),
+                        glam::ivec2(x as i32, y + 1),
+                    );
+                }
+            }
+            row_sets = next_sets;
+        }
+    }
+}
+
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub enum CellSelection {
+    Newest,     // behaves like the backtracker
+    Oldest,     // long straight corridors
+    Random,     // behaves like prim
+    Middle,     // splits the difference
+    Mixed(f32), // (newest_chance), otherwise random
+}
+impl CellSelection {
+    pub fn parse(s: &str) -> Option<CellSelection> {
+        match s.split_once(':') {
+            Some(("mixed", chance)) => chance.parse().ok().map(CellSelection::Mixed),
+            Some(_) => None,
+            None => match s {
+                "newest" => Some(CellSelection::Newest),
+                "oldest" => Some(CellSelection::Oldest),
+                "random" => Some(CellSelection::Random),
+                "middle" => Some(CellSelection::Middle),
+                _ => None,
+            },
+        }
+    }
+    pub fn name(&self) -> String {
+        match self {
+            CellSelection::Newest => "newest".to_owned(),
+            CellSelection::Oldest => "oldest".to_owned(),
+            CellSelection::Random => "random".to_owned(),
+            CellSelection::Middle => "middle".to_owned(),
+            CellSelection::Mixed(chance) => format!("mixed:{}", chance),
+        }
+    }
+    pub fn select(&self, len: usize, rng: &quad_rand::RandGenerator) -> usize {
+        match self {
+            CellSelection::Newest => len - 1,
+            CellSelection::Oldest => 0,
+            CellSelection::Random => rng.gen_range(0, len),
+            CellSelection::Middle => len / 2,
+            CellSelection::Mixed(newest_chance) => {
+                if rng.gen_range(0., 1.) < *newest_chance {
+                    len - 1
+                } else {
+                    rng.gen_range(0, len)
+                }
+            }
+        }
+    }
+}
+
+pub struct GrowingTree {
+    pub selection: CellSelection,
+}
+impl GrowingTree {
+    pub fn new(selection: CellSelection) -> GrowingTree {
+        GrowingTree { selection }
+    }
+}
+impl MazeGenerator for GrowingTree {
+    fn name(&self) -> String {
+        format!("growing-tree:{}", self.selection.name())
+    }
+    fn generate(
+        &self,
+        grid: &mut MazeGrid,
+        layout: &CellLayout,
+        start: glam::IVec2,
+        rng: &quad_rand::RandGenerator,
+    ) {
+        let mut active: Vec<glam::IVec2> = vec![start];
+        layout.carve_cell(grid, start);
+
+        while !active.is_empty() {
+            let i = self.selection.select(active.len(), rng);
+            let cell = active[i];
+            let new_cells = uncarved_neighbors(grid, layout, cell);
+            if new_cells.is_empty() {
+                active.remove(i);
+            } else {
+                let new_cell = random_item(&new_cells, rng);
+                layout.carve_passage(grid, cell, new_cell);
+                active.push(new_cell);
+            }
+        }
+    }
+}
@@ -0,0 +1,383 @@
+use crate::maze::{MazeGrid, Tile};
+
+use std::fmt;
+
+// ascii: one character per tile, one line per row
+const WALL_CHAR: char = '#';
+const FLOOR_CHAR: char = '.';
+const ROOM_CHAR: char = '_';
+
+// png: one pixel per tile, same colors as the commented out export in maze.rs used to write
+const WALL_COLOR: [u8; 4] = [255, 255, 255, 255];
+const FLOOR_COLOR: [u8; 4] = [0, 0, 0, 255];
+const ROOM_COLOR: [u8; 4] = [128, 128, 128, 255];
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+
+// tiles along either side, nothing breaks past it but a stray or corrupt file could ask for
+// gigabytes of tiles before anything noticed it wasn't a maze
+pub const MAX_SIZE: usize = 4096;
+
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum MazeFileError {
+    Empty,
+    TooLarge {
+        width: usize,
+        height: usize,
+    },
+    RaggedRow {
+        row: usize,
+        width: usize,
+        expected: usize,
+    },
+    InvalidChar {
+        row: usize,
+        col: usize,
+        c: char,
+    },
+    InvalidColor {
+        x: u32,
+        y: u32,
+        color: [u8; 4],
+    },
+    NotPng,
+    InvalidPng(String), // looks like a png but the decoder couldn't read it
+    NoOpenTiles,
+    Disconnected {
+        x: u32,
+        y: u32,
+    },
+    UnknownFormat(String),
+    Io(String),
+}
+impl fmt::Display for MazeFileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // rows and columns are 1 based so they match what a text editor shows
+        match self {
+            MazeFileError::Empty => write!(f, "the maze has no tiles"),
+            MazeFileError::TooLarge { width, height } => write!(
+                f,
+                "the maze is {}x{} tiles, at most {}x{} is supported",
+                width, height, MAX_SIZE, MAX_SIZE
+            ),
+            MazeFileError::RaggedRow {
+                row,
+                width,
+                expected,
+            } => write!(
+                f,
+                "row {} is {} tiles wide, expected {} like the first row",
+                row + 1,
+                width,
+                expected
+            ),
+            MazeFileError::InvalidChar { row, col, c } => write!(
+                f,
+                "row {} column {}: '{}' isn't a tile, use '{}' wall, '{}' floor or '{}' room",
+                row + 1,
+                col + 1,
+                c,
+                WALL_CHAR,
+                FLOOR_CHAR,
+                ROOM_CHAR
+            ),
+            MazeFileError::InvalidColor { x, y, color } => write!(
+                f,
+                "pixel ({}, {}) is {:?}, use {:?} wall, {:?} floor or {:?} room",
+                x, y, color, WALL_COLOR, FLOOR_COLOR, ROOM_COLOR
+            ),
+            MazeFileError::NotPng => write!(f, "the file isn't a png"),
+            MazeFileError::InvalidPng(err) => write!(f, "the png can't be read: {}", err),
+            MazeFileError::NoOpenTiles => write!(f, "the maze has no floor to stand on"),
+            MazeFileError::Disconnected { x, y } => write!(
+                f,
+                "pixel ({}, {}) isn't joined to the middle of the mask, passages can't jump a gap",
+                x, y
+            ),
+            MazeFileError::UnknownFormat(path) => {
+                write!(f, "'{}' should end in .txt or .png", path)
+            }
+            MazeFileError::Io(err) => write!(f, "{}", err),
+        }
+    }
+}
+impl std::error::Error for MazeFileError {}
+
+fn tile_char(tile: Tile) -> char {
+    match tile {
+        Tile::Wall => WALL_CHAR,
+        Tile::Floor => FLOOR_CHAR,
+        Tile::Room => ROOM_CHAR,
+    }
+}
+
+fn tile_color(tile: Tile) -> [u8; 4] {
+    match tile {
+        Tile::Wall => WALL_COLOR,
+        Tile::Floor => FLOOR_COLOR,
+        Tile::Room => ROOM_COLOR,
+    }
+}
+
+pub fn check_size(width: usize, height: usize) -> Result<(), MazeFileError> {
+    // width and height in tiles
+    if width == 0 || height == 0 {
+        Err(MazeFileError::Empty)
+    } else if width > MAX_SIZE || height > MAX_SIZE {
+        Err(MazeFileError::TooLarge { width, height })
+    } else {
+        Ok(())
+    }
+}
+
+fn check_open(grid: MazeGrid) -> Result<MazeGrid, MazeFileError> {
+    if grid.iter().any(|(_, tile)| tile.is_open()) {
+        Ok(grid)
+    } else {
+        Err(MazeFileError::NoOpenTiles)
+    }
+}
+
+pub fn to_ascii(grid: &MazeGrid) -> String {
+    let mut text = String::with_capacity(((grid.width() + 1) * grid.height()) as usize);
+    for y in 0..grid.height() as i32 {
+        for x in 0..grid.width() as i32 {
+            text.push(tile_char(grid.get(glam::ivec2(x, y)).unwrap()));
+        }
+        text.push('\n');
+    }
+    text
+}
+
+pub fn from_ascii(text: &str) -> Result<MazeGrid, MazeFileError> {
+    // trailing blank lines and \r\n endings are fine, anything else has to be a tile
+    let rows = text
+        .trim_end_matches(['\n', '\r'])
+        .lines()
+        .map(|line| line.trim_end_matches('\r').chars().collect::<Vec<char>>())
+        .collect::<Vec<Vec<char>>>();
+    let width = rows.first().map_or(0, |row| row.len());
+    check_size(width, rows.len())?;
+
+    let mut grid = MazeGrid::new(width as u32, rows.len() as u32, Tile::Wall);
+    for (y, row) in rows.iter().enumerate() {
+        if row.len() != width {
+            return Err(MazeFileError::RaggedRow {
+                row: y,
+                width: row.len(),
+                expected: width,
+            });
+        }
+        for (x, c) in row.iter().enumerate() {
+            let tile = match *c {
+                WALL_CHAR => Tile::Wall,
+                FLOOR_CHAR => Tile::Floor,
+                ROOM_CHAR => Tile::Room,
+                c => return Err(MazeFileError::InvalidChar { row: y, col: x, c }),
+            };
+            grid.set(glam::ivec2(x as i32, y as i32), tile);
+        }
+    }
+    check_open(grid)
+}
+
+pub fn to_image(grid: &MazeGrid) -> image::RgbaImage {
+    image::RgbaImage::from_fn(grid.width(), grid.height(), |x, y| {
+        image::Rgba(tile_color(
+            grid.get(glam::ivec2(x as i32, y as i32)).unwrap(),
+        ))
+    })
+}
+
+pub fn from_image(image: &image::RgbaImage) -> Result<MazeGrid, MazeFileError> {
+    check_size(image.width() as usize, image.height() as usize)?;
+
+    let mut grid = MazeGrid::new(image.width(), image.height(), Tile::Wall);
+    for (x, y, px) in image.enumerate_pixels() {
+        let pt = glam::ivec2(x as i32, y as i32);
+        let tile = match px.0 {
+            WALL_COLOR => Tile::Wall,
+            FLOOR_COLOR => Tile::Floor,
+            ROOM_COLOR => Tile::Room,
+            color => {
+                return Err(MazeFileError::InvalidColor {
+                    x: pt.x as u32,
+                    y: pt.y as u32,
+                    color,
+                })
+            }
+        };
+        grid.set(pt, tile);
+    }
+    check_open(grid)
+}
+
+pub fn decode_png(bytes: &[u8]) -> Result<image::RgbaImage, MazeFileError> {
+    if !bytes.starts_with(&PNG_SIGNATURE) {
+        return Err(MazeFileError::NotPng);
+    }
+    image::load_from_memory_with_format(bytes, image::ImageFormat::Png)
+        .map(|image| image.to_rgba8())
+        .map_err(|err| MazeFileError::InvalidPng(err.to_string()))
+}
+
+pub fn from_png(bytes: &[u8]) -> Result<MazeGrid, MazeFileError> {
+    from_image(&decode_png(bytes)?)
+}
+
+pub fn from_bytes(path: &str, bytes: &[u8]) -> Result<MazeGrid, MazeFileError> {
+    // the extension picks the format
+    if path.ends_with(".png") {
+        from_png(bytes)
+    } else if path.ends_with(".txt") {
+        from_ascii(&String::from_utf8_lossy(bytes))
+    } else {
+        Err(MazeFileError::UnknownFormat(path.to_owned()))
+    }
+}
+
+#[cfg(feature = "graphics")]
+pub async fn load_maze(path: &str) -> Result<MazeGrid, MazeFileError> {
+    // goes through macroquad so it also works on the web build
+    let bytes = macroquad::prelude::load_file(path)
+        .await
+        .map_err(|err| MazeFileError::Io(err.to_string()))?;
+    from_bytes(path, &bytes)
+}
+
+pub fn save_maze(grid: &MazeGrid, path: &str) -> Result<(), MazeFileError> {
+    if path.ends_with(".png") {
+        to_image(grid)
+            .save_with_format(path, image::ImageFormat::Png)
+            .map_err(|err| MazeFileError::Io(err.to_string()))
+    } else if path.ends_with(".txt") {
+        std::fs::write(path, to_ascii(grid)).map_err(|err| MazeFileError::Io(err.to_string()))
+    } else {
+        Err(MazeFileError::UnknownFormat(path.to_owned()))
+    }
+}
+
+pub fn find_start(grid: &MazeGrid) -> Option<glam::IVec2> {
+    // the open tile closest to the middle, ties go to the top left
+    let center = glam::ivec2(grid.width() as i32, grid.height() as i32) / 2;
+    grid.iter()
+        .filter(|(_, tile)| tile.is_open())
+        .map(|(pt, _)| pt)
+        .min_by_key(|pt| {
+            let d = *pt - center;
+            (d.x * d.x + d.y * d.y, pt.y, pt.x)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAZE: &str = "\
+#####
+#._.#
+#.###
+#####
+";
+
+    fn png_bytes(grid: &MazeGrid) -> Vec<u8> {
+        let mut bytes = std::io::Cursor::new(vec![]);
+        to_image(grid)
+            .write_to(&mut bytes, image::ImageFormat::Png)
+            .unwrap();
+        bytes.into_inner()
+    }
+
+    #[test]
+    fn ascii_round_trip() {
+        let grid = from_ascii(MAZE).unwrap();
+        assert_eq!((grid.width(), grid.height()), (5, 4));
+        assert_eq!(grid.get(glam::ivec2(2, 1)), Some(Tile::Room));
+        assert_eq!(grid.get(glam::ivec2(1, 2)), Some(Tile::Floor));
+        assert_eq!(to_ascii(&grid), MAZE);
+        // windows line endings and trailing blank lines read the same
+        assert_eq!(from_ascii(&MAZE.replace('\n', "\r\n")).unwrap(), grid);
+        assert_eq!(from_ascii(&format!("{}\n\n", MAZE)).unwrap(), grid);
+    }
+
+    #[test]
+    fn image_and_png_round_trip() {
+        let grid = from_ascii(MAZE).unwrap();
+        assert_eq!(from_image(&to_image(&grid)).unwrap(), grid);
+        assert_eq!(from_png(&png_bytes(&grid)).unwrap(), grid);
+        assert_eq!(from_bytes("maze.png", &png_bytes(&grid)).unwrap(), grid);
+        assert_eq!(from_bytes("maze.txt", MAZE.as_bytes()).unwrap(), grid);
+    }
+
+    #[test]
+    fn bad_ascii_is_an_error() {
+        assert_eq!(from_ascii(""), Err(MazeFileError::Empty));
+        assert_eq!(from_ascii("\n\n"), Err(MazeFileError::Empty));
+        assert_eq!(
+            from_ascii("###\n#.\n###"),
+            Err(MazeFileError::RaggedRow {
+                row: 1,
+                width: 2,
+                expected: 3
+            })
+        );
+        assert_eq!(
+            from_ascii("###\n#x#\n###"),
+            Err(MazeFileError::InvalidChar {
+                row: 1,
+                col: 1,
+                c: 'x'
+            })
+        );
+        assert_eq!(from_ascii("###\n###"), Err(MazeFileError::NoOpenTiles));
+        assert_eq!(
+            from_ascii(&".".repeat(MAX_SIZE + 1)),
+            Err(MazeFileError::TooLarge {
+                width: MAX_SIZE + 1,
+                height: 1
+            })
+        );
+        assert_eq!(
+            from_bytes("maze.bmp", MAZE.as_bytes()),
+            Err(MazeFileError::UnknownFormat("maze.bmp".to_owned()))
+        );
+    }
+
+    #[test]
+    fn bad_images_are_an_error() {
+        let mut image = to_image(&from_ascii(MAZE).unwrap());
+        image.put_pixel(3, 2, image::Rgba([255, 0, 0, 255]));
+        assert_eq!(
+            from_image(&image),
+            Err(MazeFileError::InvalidColor {
+                x: 3,
+                y: 2,
+                color: [255, 0, 0, 255]
+            })
+        );
+        assert_eq!(
+            from_image(&image::RgbaImage::new(0, 3)),
+            Err(MazeFileError::Empty)
+        );
+        let walls = image::RgbaImage::from_pixel(2, 2, image::Rgba(WALL_COLOR));
+        assert_eq!(from_image(&walls), Err(MazeFileError::NoOpenTiles));
+    }
+
+    #[test]
+    fn bad_pngs_are_an_error() {
+        assert_eq!(from_png(MAZE.as_bytes()), Err(MazeFileError::NotPng));
+        // a png signature followed by junk used to panic in the decoder
+        let mut corrupt = PNG_SIGNATURE.to_vec();
+        corrupt.extend(b"not really a png");
+        assert!(matches!(
+            from_png(&corrupt),
+            Err(MazeFileError::InvalidPng(_))
+        ));
+        let mut truncated = png_bytes(&from_ascii(MAZE).unwrap());
+        truncated.truncate(truncated.len() / 2);
+        assert!(matches!(
+            from_png(&truncated),
+            Err(MazeFileError::InvalidPng(_))
+        ));
+    }
+}
@@ -0,0 +1,237 @@
+use crate::maze::{self, CellLayout, MazeGrid, Tile, NEIGHBOR_OFFSETS};
+use crate::maze_generators::MazeGenerator;
+use crate::maze_io::{self, MazeFileError};
+use crate::maze_rooms;
+
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct MazeMask {
+    // one entry per maze cell, not per tile, the maze only goes where it's true
+    pub cols: i32,
+    pub rows: i32,
+    cells: Vec<bool>,
+}
+impl MazeMask {
+    pub fn new(rows: &[Vec<bool>]) -> Result<MazeMask, MazeFileError> {
+        let expected = rows.first().map_or(0, |row| row.len());
+        if expected == 0 {
+            return Err(MazeFileError::Empty);
+        }
+        // the grid is two tiles per cell plus a border, and has to fit like a --maze file would
+        maze_io::check_size(2 * expected + 1, 2 * rows.len() + 1)?;
+        if let Some((row, cells)) = rows.iter().enumerate().find(|(_, r)| r.len() != expected) {
+            return Err(MazeFileError::RaggedRow {
+                row,
+                width: cells.len(),
+                expected,
+            });
+        }
+        let cells = rows.concat();
+        if !cells.contains(&true) {
+            return Err(MazeFileError::NoOpenTiles);
+        }
+        let mask = MazeMask {
+            cols: expected as i32,
+            rows: rows.len() as i32,
+            cells,
+        };
+        // passages can't jump a gap, so anything off on its own would be sealed in
+        let joined = mask.joined();
+        match (0..mask.cells.len()).find(|i| mask.cells[*i] && !joined[*i]) {
+            Some(i) => Err(MazeFileError::Disconnected {
+                x: i as u32 % expected as u32,
+                y: i as u32 / expected as u32,
+            }),
+            None => Ok(mask),
+        }
+    }
+    pub fn from_image(image: &image::RgbaImage) -> Result<MazeMask, MazeFileError> {
+        // one pixel per cell, dark opaque pixels are inside, so a black shape on white works
+        let rows = image
+            .rows()
+            .map(|row| {
+                row.map(|px| {
+                    let [r, g, b, a] = px.0.map(u32::from);
+                    a * 2 >= 255 && (r + g + b) * 2 < 255 * 3
+                })
+                .collect()
+            })
+            .collect::<Vec<Vec<bool>>>();
+        MazeMask::new(&rows)
+    }
+    pub fn from_png(bytes: &[u8]) -> Result<MazeMask, MazeFileError> {
+        MazeMask::from_image(&maze_io::decode_png(bytes)?)
+    }
+    pub fn contains(&self, cell: glam::IVec2) -> bool {
+        cell.x >= 0
+            && cell.y >= 0
+            && cell.x < self.cols
+            && cell.y < self.rows
+            && self.cells[(cell.y * self.cols + cell.x) as usize]
+    }
+    pub fn grid_size(&self) -> glam::UVec2 {
+        glam::uvec2(self.cols as u32, self.rows as u32) * 2 + 1
+    }
+    fn start(&self) -> glam::IVec2 {
+        // the inside cell nearest the middle
+        let center = glam::vec2(self.cols as f32, self.rows as f32) / 2.;
+        (0..self.rows)
+            .flat_map(|y| (0..self.cols).map(move |x| glam::ivec2(x, y)))
+            .filter(|cell| self.contains(*cell))
+            .min_by(|a, b| {
+                let dist = |cell: &glam::IVec2| (cell.as_vec2() + 0.5 - center).length_squared();
+                dist(a).total_cmp(&dist(b))
+            })
+            .unwrap()
+    }
+    fn joined(&self) -> Vec<bool> {
+        // return: for each cell, whether it's inside and joined to the start
+        let start = self.start();
+        let idx = |cell: glam::IVec2| (cell.y * self.cols + cell.x) as usize;
+        let mut joined = vec![false; self.cells.len()];
+        let mut stack = vec![start];
+        joined[idx(start)] = true;
+        while let Some(cell) = stack.pop() {
+            for offset in NEIGHBOR_OFFSETS.iter() {
+                let new_cell = cell + *offset;
+                if self.contains(new_cell) && !joined[idx(new_cell)] {
+                    joined[idx(new_cell)] = true;
+                    stack.push(new_cell);
+                }
+            }
+        }
+        joined
+    }
+    pub fn layout(&self) -> (CellLayout, glam::IVec2) {
+        // return: the layout and start cell
+        let layout = CellLayout {
+            origin: glam::ivec2(1, 1),
+            cols: self.cols,
+            rows: self.rows,
+            mask: Some(self.cells.clone()),
+        };
+        (layout, self.start())
+    }
+}
+
+pub fn create_masked_maze(
+    mask: &MazeMask,
+    seed: u64,
+    generator: &dyn MazeGenerator,
+) -> (MazeGrid, glam::IVec2) {
+    // like maze::create_maze_map but the grid is sized to fit the mask
+    // return: the maze and its start tile
+    let rng = maze::create_rng(seed);
+    let (layout, start) = mask.layout();
+    let size = mask.grid_size();
+
+    let mut grid = MazeGrid::new(size.x, size.y, Tile::Wall);
+    generator.generate(&mut grid, &layout, start, &rng);
+    // a narrow neck in the mask can split eller's rows, this joins them back up
+    maze_rooms::connect_regions(&mut grid, &layout, start, &rng);
+
+    (grid, layout.to_tile(start))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::maze_generators;
+    use crate::maze_paths::DistanceField;
+
+    fn mask(rows: &str) -> Result<MazeMask, MazeFileError> {
+        // x is inside
+        let rows = rows
+            .lines()
+            .map(|row| row.chars().map(|c| c == 'x').collect())
+            .collect::<Vec<Vec<bool>>>();
+        MazeMask::new(&rows)
+    }
+
+    const PLUS: &str = "\
+..x..
+..x..
+xxxxx
+..x..
+..x..
+";
+
+    #[test]
+    fn the_maze_fills_the_mask() {
+        let mask = mask(PLUS).unwrap();
+        assert_eq!(mask.grid_size(), glam::uvec2(11, 11));
+        let (layout, start) = mask.layout();
+        assert_eq!(start, glam::ivec2(2, 2));
+        assert_eq!(layout.len(), 25);
+        for name in ["backtracker", "prim", "kruskal", "wilson", "eller"] {
+            let generator = maze_generators::parse_generator(name).unwrap();
+            let (grid, start_tile) = create_masked_maze(&mask, 3, generator.as_ref());
+            assert_eq!(start_tile, glam::ivec2(5, 5));
+            let field = DistanceField::new(&grid, start_tile);
+            for i in 0..layout.len() {
+                let cell = layout.cell_at(i);
+                let tile = layout.to_tile(cell);
+                assert_eq!(grid.get(tile).unwrap().is_open(), mask.contains(cell));
+                assert_eq!(
+                    field.get(tile).is_some() || tile == start_tile,
+                    mask.contains(cell),
+                    "{} missed {}",
+                    name,
+                    cell
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn every_inside_cell_has_to_join_up() {
+        assert!(mask(PLUS).is_ok());
+        // corners touching isn't enough
+        assert_eq!(
+            mask("x.\n.x"),
+            Err(MazeFileError::Disconnected { x: 1, y: 1 })
+        );
+        assert_eq!(
+            mask("xx..x\nxx...\n....."),
+            Err(MazeFileError::Disconnected { x: 4, y: 0 })
+        );
+    }
+
+    #[test]
+    fn bad_masks_are_an_error() {
+        assert_eq!(mask(""), Err(MazeFileError::Empty));
+        assert_eq!(mask("..\n.."), Err(MazeFileError::NoOpenTiles));
+        assert_eq!(
+            mask("xx\nx"),
+            Err(MazeFileError::RaggedRow {
+                row: 1,
+                width: 1,
+                expected: 2
+            })
+        );
+        // the limit is on the maze's tiles, not the mask's cells
+        let cols = maze_io::MAX_SIZE / 2;
+        assert!(MazeMask::new(&[vec![true; cols - 1]]).is_ok());
+        assert_eq!(
+            MazeMask::new(&[vec![true; cols]]),
+            Err(MazeFileError::TooLarge {
+                width: 2 * cols + 1,
+                height: 3
+            })
+        );
+    }
+
+    #[test]
+    fn dark_opaque_pixels_are_inside() {
+        let pixels = [
+            [0, 0, 0, 255],
+            [60, 60, 60, 200],
+            [255, 255, 255, 255],
+            [0, 0, 0, 0],
+        ];
+        let mut image = image::RgbaImage::new(4, 1);
+        for (x, color) in pixels.iter().enumerate() {
+            image.put_pixel(x as u32, 0, image::Rgba(*color));
+        }
+        assert_eq!(MazeMask::from_image(&image).unwrap(), mask("xx..").unwrap());
+    }
+}
@@ -0,0 +1,212 @@
+use crate::maze::MazeGrid;
+use crate::maze_paths::DistanceField;
+
+use std::collections::HashSet;
+use std::fmt;
+
+#[derive(PartialEq, Debug, Copy, Clone, Default)]
+pub struct MazeMetrics {
+    // room tiles count like any other open tile, so a room shows up as a clump of junctions
+    pub open_tiles: usize,
+    pub dead_ends: usize,      // open tiles with one way out
+    pub junctions: usize,      // open tiles with three or more ways out
+    pub average_corridor: f32, // tiles in a run of two way tiles
+    pub branching_factor: f32, // ways on from a junction, not counting the way in
+    pub solution_length: u32,  // steps from the start to the exit
+    pub river: f32, // tiles from a dead end back to its junction, high when dead ends are long
+    pub turns: usize, // corners along the solution
+}
+impl MazeMetrics {
+    pub fn new(grid: &MazeGrid, start: glam::IVec2, exit: glam::IVec2) -> Option<MazeMetrics> {
+        // return: None when the exit can't be reached from start
+        let solution = DistanceField::new(grid, start).path_to(exit)?;
+        let degree =
+            |tile: glam::IVec2| grid.neighbors(tile, 1).filter(|t| grid.is_open(*t)).count();
+        let open = grid
+            .iter()
+            .filter(|(_, tile)| tile.is_open())
+            .map(|(pt, _)| pt)
+            .collect::<Vec<glam::IVec2>>();
+
+        let dead_ends = open
+            .iter()
+            .filter(|tile| degree(**tile) == 1)
+            .copied()
+            .collect::<Vec<glam::IVec2>>();
+        let junction_ways = open
+            .iter()
+            .map(|tile| degree(*tile))
+            .filter(|ways| *ways >= 3)
+            .collect::<Vec<usize>>();
+
+        // corridors are the connected runs of two way tiles
+        let mut corridors = vec![];
+        let mut seen = HashSet::new();
+        for tile in open.iter().filter(|tile| degree(**tile) == 2) {
+            if !seen.insert(*tile) {
+                continue;
+            }
+            let mut len = 0;
+            let mut stack = vec![*tile];
+            while let Some(tile) = stack.pop() {
+                len += 1;
+                for new_tile in grid.neighbors(tile, 1) {
+                    if grid.is_open(new_tile) && degree(new_tile) == 2 && seen.insert(new_tile) {
+                        stack.push(new_tile);
+                    }
+                }
+            }
+            corridors.push(len);
+        }
+
+        // walk back from each dead end until the corridor branches off something
+        let rivers = dead_ends
+            .iter()
+            .map(|dead_end| {
+                let mut len = 0;
+                let mut last = *dead_end;
+                let mut tile = *dead_end;
+                loop {
+                    let next = grid
+                        .neighbors(tile, 1)
+                        .find(|t| grid.is_open(*t) && *t != last);
+                    match next {
+                        Some(next) if degree(tile) <= 2 => {
+                            len += 1;
+                            last = tile;
+                            tile = next;
+                        }
+                        _ => break len,
+                    }
+                }
+            })
+            .collect::<Vec<usize>>();
+
+        let turns = solution
+            .windows(3)
+            .filter(|w| w[1] - w[0] != w[2] - w[1])
+            .count();
+
+        Some(MazeMetrics {
+            open_tiles: open.len(),
+            dead_ends: dead_ends.len(),
+            junctions: junction_ways.len(),
+            average_corridor: mean(&corridors),
+            branching_factor: mean(
+                &junction_ways
+                    .iter()
+                    .map(|ways| ways - 1)
+                    .collect::<Vec<_>>(),
+            ),
+            solution_length: solution.len() as u32 - 1,
+            river: mean(&rivers),
+            turns,
+        })
+    }
+    pub fn average(metrics: &[MazeMetrics]) -> MazeMetrics {
+        // counts are rounded, so the average of a batch can be printed like one maze
+        let avg = |f: fn(&MazeMetrics) -> f32| {
+            metrics.iter().map(f).sum::<f32>() / metrics.len().max(1) as f32
+        };
+        MazeMetrics {
+            open_tiles: avg(|m| m.open_tiles as f32).round() as usize,
+            dead_ends: avg(|m| m.dead_ends as f32).round() as usize,
+            junctions: avg(|m| m.junctions as f32).round() as usize,
+            average_corridor: avg(|m| m.average_corridor),
+            branching_factor: avg(|m| m.branching_factor),
+            solution_length: avg(|m| m.solution_length as f32).round() as u32,
+            river: avg(|m| m.river),
+            turns: avg(|m| m.turns as f32).round() as usize,
+        }
+    }
+    pub fn header() -> String {
+        format!(
+            "{:>6} {:>9} {:>9} {:>8} {:>9} {:>8} {:>6} {:>6}",
+            "open", "dead ends", "junctions", "corridor", "branching", "solution", "river", "turns"
+        )
+    }
+    pub fn row(&self) -> String {
+        // lines up under header
+        format!(
+            "{:>6} {:>9} {:>9} {:>8.2} {:>9.2} {:>8} {:>6.2} {:>6}",
+            self.open_tiles,
+            self.dead_ends,
+            self.junctions,
+            self.average_corridor,
+            self.branching_factor,
+            self.solution_length,
+            self.river,
+            self.turns
+        )
+    }
+}
+impl fmt::Display for MazeMetrics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "open tiles:       {}", self.open_tiles)?;
+        writeln!(f, "dead ends:        {}", self.dead_ends)?;
+        writeln!(f, "junctions:        {}", self.junctions)?;
+        writeln!(f, "average corridor: {:.2}", self.average_corridor)?;
+        writeln!(f, "branching factor: {:.2}", self.branching_factor)?;
+        writeln!(f, "solution length:  {}", self.solution_length)?;
+        writeln!(f, "river:            {:.2}", self.river)?;
+        write!(f, "turns:            {}", self.turns)
+    }
+}
+
+fn mean(values: &[usize]) -> f32 {
+    if values.is_empty() {
+        0.
+    } else {
+        values.iter().sum::<usize>() as f32 / values.len() as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::maze_io;
+
+    // one junction with three dead ends off it
+    const MAZE: &str = "\
+#######
+#.....#
+#.#.#.#
+#.#.#.#
+#######
+";
+
+    fn metrics() -> MazeMetrics {
+        let grid = maze_io::from_ascii(MAZE).unwrap();
+        MazeMetrics::new(&grid, glam::ivec2(1, 3), glam::ivec2(5, 3)).unwrap()
+    }
+
+    #[test]
+    fn counts_a_hand_built_maze() {
+        assert_eq!(
+            metrics(),
+            MazeMetrics {
+                open_tiles: 11,
+                dead_ends: 3,
+                junctions: 1,
+                // (1, 2) to (2, 1), (4, 1) to (5, 2), and (3, 2)
+                average_corridor: 7. / 3.,
+                branching_factor: 2.,
+                solution_length: 8,
+                river: 10. / 3.,
+                turns: 2,
+            }
+        );
+        let grid = maze_io::from_ascii(MAZE).unwrap();
+        assert_eq!(
+            MazeMetrics::new(&grid, glam::ivec2(1, 3), glam::ivec2(2, 2)),
+            None
+        );
+    }
+
+    #[test]
+    fn the_average_of_one_maze_is_that_maze() {
+        let metrics = metrics();
+        assert_eq!(MazeMetrics::average(&[metrics; 3]), metrics);
+        assert_eq!(MazeMetrics::average(&[]), MazeMetrics::default());
+    }
+}
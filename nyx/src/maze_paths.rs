@@ -0,0 +1,379 @@
+use crate::maze::{MazeGrid, NEIGHBOR_OFFSETS};
+use crate::maze_chunks::ChunkedMaze;
+
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, VecDeque};
+
+pub trait Walkable {
+    fn is_open(&self, tile: glam::IVec2) -> bool;
+    fn revision(&self) -> u64;
+}
+impl Walkable for MazeGrid {
+    fn is_open(&self, tile: glam::IVec2) -> bool {
+        MazeGrid::is_open(self, tile)
+    }
+    fn revision(&self) -> u64 {
+        MazeGrid::revision(self)
+    }
+}
+impl Walkable for ChunkedMaze {
+    fn is_open(&self, tile: glam::IVec2) -> bool {
+        // unloaded chunks are treated as solid
+        self.get(tile).is_some_and(|tile| tile.is_open())
+    }
+    fn revision(&self) -> u64 {
+        ChunkedMaze::revision(self)
+    }
+}
+
+fn open_neighbors<'a, W: Walkable + ?Sized>(
+    maze: &'a W,
+    tile: glam::IVec2,
+) -> impl Iterator<Item = glam::IVec2> + 'a {
+    NEIGHBOR_OFFSETS
+        .iter()
+        .map(move |offset| tile + *offset)
+        .filter(move |new_tile| maze.is_open(*new_tile))
+}
+
+#[derive(Debug, Clone)]
+pub struct DistanceField {
+    pub origin: glam::IVec2,
+    pub revision: u64, // revision of the maze the distances are good for
+    distances: HashMap<glam::IVec2, u32>,
+}
+impl DistanceField {
+    pub fn new<W: Walkable + ?Sized>(maze: &W, origin: glam::IVec2) -> DistanceField {
+        let mut field = DistanceField {
+            origin,
+            revision: maze.revision(),
+            distances: HashMap::new(),
+        };
+        if maze.is_open(origin) {
+            field.distances.insert(origin, 0);
+            field.spread(maze, VecDeque::from(vec![origin]));
+        }
+        field
+    }
+    fn spread<W: Walkable + ?Sized>(&mut self, maze: &W, mut queue: VecDeque<glam::IVec2>) {
+        // breadth first, but a tile is requeued whenever a shorter route to it turns up
+        while let Some(tile) = queue.pop_front() {
+            let d = self.distances[&tile] + 1;
+            for new_tile in open_neighbors(maze, tile) {
+                if self.distances.get(&new_tile).is_none_or(|old_d| d < *old_d) {
+                    self.distances.insert(new_tile, d);
+                    queue.push_back(new_tile);
+                }
+            }
+        }
+    }
+    pub fn get(&self, tile: glam::IVec2) -> Option<u32> {
+        self.distances.get(&tile).copied()
+    }
+    pub fn len(&self) -> usize {
+        self.distances.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.distances.is_empty()
+    }
+    pub fn iter(&self) -> impl Iterator<Item = (glam::IVec2, u32)> + '_ {
+        self.distances.iter().map(|(tile, d)| (*tile, *d))
+    }
+    pub fn farthest(&self) -> Option<(glam::IVec2, u32)> {
+        // ties go to the top left tile so the answer doesn't depend on hash order
+        self.iter()
+            .max_by_key(|(tile, d)| (*d, Reverse(tile.y), Reverse(tile.x)))
+    }
+    pub fn path_to(&self, tile: glam::IVec2) -> Option<Vec<glam::IVec2>> {
+        // return: tiles from origin to tile, both included
+        let mut d = self.get(tile)?;
+        let mut path = vec![tile];
+        let mut current = tile;
+        while d > 0 {
+            current = NEIGHBOR_OFFSETS
+                .iter()
+                .map(|offset| current + *offset)
+                .find(|new_tile| self.get(*new_tile) == Some(d - 1))?;
+            path.push(current);
+            d -= 1;
+        }
+        path.reverse();
+        Some(path)
+    }
+    pub fn walls_opened<W: Walkable + ?Sized>(&mut self, maze: &W, tiles: &[glam::IVec2]) {
+        // opening walls can only make routes shorter, so relax outward from the new tiles
+        let mut queue = VecDeque::new();
+        for tile in tiles.iter().filter(|tile| maze.is_open(**tile)) {
+            let best = open_neighbors(maze, *tile)
+                .filter_map(|new_tile| self.get(new_tile))
+                .min();
+            if let Some(d) = best.map(|d| d + 1) {
+                if self.get(*tile).is_none_or(|old_d| d < old_d) {
+                    self.distances.insert(*tile, d);
+                    queue.push_back(*tile);
+                }
+            }
+        }
+        self.spread(maze, queue);
+        self.revision = maze.revision();
+    }
+    pub fn walls_closed<W: Walkable + ?Sized>(
+        &mut self,
+        maze: &W,
+        tiles: &[glam::IVec2],
+    ) -> Vec<glam::IVec2> {
+        // closing walls can only make routes longer, so drop every tile whose route went through
+        // a closed one, nearest first, then fill the dropped tiles back in from around them
+        // return: tiles the field doesn't reach any more
+        let mut queue = BinaryHeap::new();
+        for tile in tiles {
+            if let Some(d) = self.get(*tile) {
+                queue.push(Reverse((d, tile.x, tile.y)));
+            }
+        }
+        let mut dropped = vec![];
+        while let Some(Reverse((d, x, y))) = queue.pop() {
+            let tile = glam::ivec2(x, y);
+            if self.get(tile) != Some(d) {
+                continue;
+            }
+            // every tile nearer than d has been settled, so a neighbor at d - 1 still holds
+            let held = maze.is_open(tile)
+                && (d == 0
+                    || open_neighbors(maze, tile)
+                        .any(|new_tile| self.get(new_tile) == Some(d - 1)));
+            if held {
+                continue;
+            }
+            self.distances.remove(&tile);
+            dropped.push(tile);
+            for offset in NEIGHBOR_OFFSETS.iter() {
+                let new_tile = tile + *offset;
+                if self.get(new_tile) == Some(d + 1) {
+                    queue.push(Reverse((d + 1, new_tile.x, new_tile.y)));
+                }
+            }
+        }
+
+        let mut refill = VecDeque::new();
+        for tile in dropped.iter().filter(|tile| maze.is_open(**tile)) {
+            let best = open_neighbors(maze, *tile)
+                .filter_map(|new_tile| self.get(new_tile))
+                .min();
+            if let Some(d) = best.map(|d| d + 1) {
+                self.distances.insert(*tile, d);
+                refill.push_back(*tile);
+            }
+        }
+        self.spread(maze, refill);
+        self.revision = maze.revision();
+        dropped.retain(|tile| self.get(*tile).is_none());
+        dropped
+    }
+}
+
+pub fn find_path<W: Walkable + ?Sized>(
+    maze: &W,
+    from: glam::IVec2,
+    to: glam::IVec2,
+) -> Option<Vec<glam::IVec2>> {
+    // a* with manhattan distance
+    // return: tiles from from to to, both included
+    if !maze.is_open(from) || !maze.is_open(to) {
+        return None;
+    }
+    let h = |tile: glam::IVec2| ((to - tile).abs().x + (to - tile).abs().y) as u32;
+
+    let mut open_set = BinaryHeap::new();
+    let mut came_from: HashMap<glam::IVec2, glam::IVec2> = HashMap::new();
+    let mut g_scores: HashMap<glam::IVec2, u32> = HashMap::new();
+    g_scores.insert(from, 0);
+    open_set.push(Reverse((h(from), 0, from.x, from.y)));
+
+    while let Some(Reverse((_, g, x, y))) = open_set.pop() {
+        let tile = glam::ivec2(x, y);
+        if tile == to {
+            let mut path = vec![tile];
+            let mut current = tile;
+            while let Some(prev) = came_from.get(&current) {
+                current = *prev;
+                path.push(current);
+            }
+            path.reverse();
+            return Some(path);
+        }
+        if g > g_scores[&tile] {
+            continue; // already found a shorter way here
+        }
+        for new_tile in open_neighbors(maze, tile) {
+            let new_g = g + 1;
+            if g_scores.get(&new_tile).is_none_or(|old_g| new_g < *old_g) {
+                g_scores.insert(new_tile, new_g);
+                came_from.insert(new_tile, tile);
+                open_set.push(Reverse((
+                    new_g + h(new_tile),
+                    new_g,
+                    new_tile.x,
+                    new_tile.y,
+                )));
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::maze::{self, Tile};
+    use crate::{maze_generators, maze_io};
+
+    // two routes as long as each other from the top left to the middle, and one tile walled off
+    const MAZE: &str = "\
+#######
+#.....#
+#.###.#
+#.#...#
+#.#.###
+#...#.#
+#######
+";
+
+    fn grid() -> MazeGrid {
+        maze_io::from_ascii(MAZE).unwrap()
+    }
+
+    fn assert_same(a: &DistanceField, b: &DistanceField) {
+        let mut a = a.iter().collect::<Vec<_>>();
+        let mut b = b.iter().collect::<Vec<_>>();
+        a.sort_by_key(|(tile, _)| (tile.y, tile.x));
+        b.sort_by_key(|(tile, _)| (tile.y, tile.x));
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn distance_field_walks_the_open_tiles() {
+        let grid = grid();
+        let field = DistanceField::new(&grid, glam::ivec2(1, 1));
+        assert_eq!(
+            field.len(),
+            grid.iter().filter(|(_, t)| t.is_open()).count() - 1
+        );
+        assert_eq!(field.get(glam::ivec2(1, 1)), Some(0));
+        assert_eq!(field.get(glam::ivec2(5, 1)), Some(4));
+        assert_eq!(field.get(glam::ivec2(3, 3)), Some(8));
+        assert_eq!(field.get(glam::ivec2(3, 5)), Some(6));
+        assert_eq!(field.get(glam::ivec2(0, 0)), None);
+        // (5, 5) is walled off on its own
+        assert_eq!(field.get(glam::ivec2(5, 5)), None);
+        assert_eq!(field.farthest(), Some((glam::ivec2(3, 3), 8)));
+        assert!(DistanceField::new(&grid, glam::ivec2(0, 0)).is_empty());
+    }
+
+    #[test]
+    fn path_to_follows_the_field() {
+        let field = DistanceField::new(&grid(), glam::ivec2(1, 1));
+        let path = field.path_to(glam::ivec2(3, 3)).unwrap();
+        assert_eq!(path.len(), 9);
+        assert_eq!(path.first(), Some(&glam::ivec2(1, 1)));
+        assert_eq!(path.last(), Some(&glam::ivec2(3, 3)));
+        for pair in path.windows(2) {
+            let d = (pair[1] - pair[0]).abs();
+            assert_eq!(d.x + d.y, 1);
+        }
+        assert_eq!(field.path_to(glam::ivec2(5, 5)), None);
+    }
+
+    #[test]
+    fn find_path_is_as_short_as_the_field() {
+        let grid = grid();
+        let field = DistanceField::new(&grid, glam::ivec2(1, 1));
+        for (tile, d) in field.iter() {
+            let path = find_path(&grid, glam::ivec2(1, 1), tile).unwrap();
+            assert_eq!(path.len() as u32, d + 1);
+            assert!(path.iter().all(|tile| grid.is_open(*tile)));
+        }
+        assert_eq!(find_path(&grid, glam::ivec2(1, 1), glam::ivec2(5, 5)), None);
+        assert_eq!(find_path(&grid, glam::ivec2(0, 0), glam::ivec2(1, 1)), None);
+    }
+
+    #[test]
+    fn walls_opened_matches_a_new_field() {
+        let mut grid = grid();
+        let origin = glam::ivec2(1, 1);
+        let mut field = DistanceField::new(&grid, origin);
+
+        // a shortcut makes (3, 3) closer
+        grid.set(glam::ivec2(2, 3), Tile::Floor);
+        assert_ne!(field.revision, grid.revision());
+        field.walls_opened(&grid, &[glam::ivec2(2, 3)]);
+        assert_eq!(field.get(glam::ivec2(3, 3)), Some(4));
+        assert_eq!(field.revision, grid.revision());
+        assert_same(&field, &DistanceField::new(&grid, origin));
+
+        // and this one lets the field into the walled off tile
+        grid.set(glam::ivec2(5, 4), Tile::Floor);
+        field.walls_opened(&grid, &[glam::ivec2(5, 4)]);
+        assert_eq!(field.get(glam::ivec2(5, 5)), Some(8));
+        assert_same(&field, &DistanceField::new(&grid, origin));
+    }
+
+    #[test]
+    fn walls_closed_matches_a_new_field() {
+        let mut grid = grid();
+        let origin = glam::ivec2(1, 1);
+        let mut field = DistanceField::new(&grid, origin);
+
+        // the long way round to (3, 3) is as short as it gets once (1, 4) is shut
+        grid.set(glam::ivec2(1, 4), Tile::Wall);
+        let dropped = field.walls_closed(&grid, &[glam::ivec2(1, 4)]);
+        assert_eq!(field.revision, grid.revision());
+        assert_eq!(field.get(glam::ivec2(3, 3)), Some(8));
+        assert_eq!(field.get(glam::ivec2(3, 5)), Some(10));
+        assert_eq!(dropped, vec![glam::ivec2(1, 4)]);
+        assert_same(&field, &DistanceField::new(&grid, origin));
+
+        // and (5, 1) cuts off everything past it
+        grid.set(glam::ivec2(5, 1), Tile::Wall);
+        let dropped = field.walls_closed(&grid, &[glam::ivec2(5, 1)]);
+        assert_eq!(dropped.len(), 9);
+        assert_eq!(field.len(), 6);
+        assert_same(&field, &DistanceField::new(&grid, origin));
+
+        // closing the origin leaves nothing
+        grid.set(origin, Tile::Wall);
+        assert_eq!(field.walls_closed(&grid, &[origin]).len(), 6);
+        assert!(field.is_empty());
+    }
+
+    #[test]
+    fn random_opens_and_closes_match_a_new_field() {
+        let generator = maze_generators::parse_generator("backtracker").unwrap();
+        let mut grid = maze::create_maze_map(21., glam::vec2(11., 11.), 4, generator.as_ref());
+        let origin = glam::ivec2(11, 11);
+        let mut field = DistanceField::new(&grid, origin);
+        let rng = maze::create_rng(4);
+        for i in 0..400 {
+            let tile = glam::ivec2(rng.gen_range(1, 20), rng.gen_range(1, 20));
+            if tile == origin {
+                continue;
+            }
+            let before = field.iter().map(|(tile, _)| tile).collect::<Vec<_>>();
+            if grid.is_open(tile) {
+                grid.set(tile, Tile::Wall);
+                let mut dropped = field.walls_closed(&grid, &[tile]);
+                let mut lost = before
+                    .into_iter()
+                    .filter(|tile| field.get(*tile).is_none())
+                    .collect::<Vec<_>>();
+                lost.sort_by_key(|tile| (tile.y, tile.x));
+                dropped.sort_by_key(|tile| (tile.y, tile.x));
+                assert_eq!(dropped, lost, "close {} at step {}", tile, i);
+            } else {
+                grid.set(tile, Tile::Floor);
+                field.walls_opened(&grid, &[tile]);
+            }
+            assert_same(&field, &DistanceField::new(&grid, origin));
+        }
+    }
+}
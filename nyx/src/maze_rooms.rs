@@ -0,0 +1,291 @@
+use crate::maze::{CellLayout, MazeGrid, Tile, NEIGHBOR_OFFSETS};
+
+use std::collections::HashSet;
+
+#[derive(PartialEq, Eq, Debug, Copy, Clone)]
+pub enum RoomOrder {
+    BeforeMaze, // the generator winds corridors around the rooms
+    AfterMaze,  // rooms are punched through finished corridors
+}
+
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub struct RoomConfig {
+    pub attempts: usize, // placements tried, ones that crowd another room are thrown out
+    pub min_size: i32,   // in cells
+    pub max_size: i32,   // in cells
+    pub irregular_chance: f32,
+    pub order: RoomOrder,
+}
+impl RoomConfig {
+    pub fn new(attempts: usize, order: RoomOrder) -> RoomConfig {
+        RoomConfig {
+            attempts,
+            min_size: 2,
+            max_size: 4,
+            irregular_chance: 0.3,
+            order,
+        }
+    }
+}
+impl Default for RoomConfig {
+    fn default() -> RoomConfig {
+        RoomConfig::new(0, RoomOrder::AfterMaze)
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct Room {
+    pub cells: Vec<glam::IVec2>,
+}
+impl Room {
+    pub fn contains(&self, cell: glam::IVec2) -> bool {
+        self.cells.contains(&cell)
+    }
+    pub fn tiles(&self, layout: &CellLayout) -> Vec<glam::IVec2> {
+        self.cells
+            .iter()
+            .map(|cell| layout.to_tile(*cell))
+            .collect()
+    }
+}
+
+fn rect_cells(min: glam::IVec2, size: glam::IVec2) -> impl Iterator<Item = glam::IVec2> {
+    (0..size.y).flat_map(move |y| (0..size.x).map(move |x| min + glam::ivec2(x, y)))
+}
+
+fn random_size(config: &RoomConfig, rng: &quad_rand::RandGenerator) -> glam::IVec2 {
+    glam::ivec2(
+        rng.gen_range(config.min_size, config.max_size + 1),
+        rng.gen_range(config.min_size, config.max_size + 1),
+    )
+}
+
+fn random_room(
+    layout: &CellLayout,
+    config: &RoomConfig,
+    rng: &quad_rand::RandGenerator,
+) -> Option<Room> {
+    // rooms stay off the outer ring of cells so corridors can always run around them
+    let interior = |cell: glam::IVec2| {
+        cell.x >= 1 && cell.y >= 1 && cell.x < layout.cols - 1 && cell.y < layout.rows - 1
+    };
+    let size = random_size(config, rng);
+    if size.x > layout.cols - 2 || size.y > layout.rows - 2 {
+        return None;
+    }
+    let min = glam::ivec2(
+        rng.gen_range(1, layout.cols - size.x),
+        rng.gen_range(1, layout.rows - size.y),
+    );
+    let mut cells = rect_cells(min, size).collect::<Vec<glam::IVec2>>();
+
+    if rng.gen_range(0., 1.) < config.irregular_chance {
+        // bolt on a few more rectangles, each one overlapping a cell we already have
+        for _ in 0..rng.gen_range(1, 3) {
+            let anchor = cells[rng.gen_range(0, cells.len())];
+            let size = random_size(config, rng);
+            let min = anchor - glam::ivec2(rng.gen_range(0, size.x), rng.gen_range(0, size.y));
+            for cell in rect_cells(min, size) {
+                if interior(cell) && !cells.contains(&cell) {
+                    cells.push(cell);
+                }
+            }
+        }
+    }
+
+    Some(Room { cells })
+}
+
+pub fn carve_room(grid: &mut MazeGrid, layout: &CellLayout, room: &Room) {
+    // a tile between cells is only room floor when every cell around it is in the room
+    for cell in room.cells.iter() {
+        for offset in [
+            glam::ivec2(0, 0),
+            glam::ivec2(1, 0),
+            glam::ivec2(0, 1),
+            glam::ivec2(1, 1),
+        ] {
+            let corners = [
+                *cell,
+                *cell + glam::ivec2(offset.x, 0),
+                *cell + glam::ivec2(0, offset.y),
+                *cell + offset,
+            ];
+            if corners.iter().all(|corner| room.contains(*corner)) {
+                grid.set(layout.to_tile(*cell) + offset, Tile::Room);
+            }
+        }
+    }
+}
+
+pub fn place_rooms(
+    grid: &mut MazeGrid,
+    layout: &CellLayout,
+    config: &RoomConfig,
+    start: glam::IVec2,
+    rng: &quad_rand::RandGenerator,
+) -> Vec<Room> {
+    let mut rooms: Vec<Room> = vec![];
+    // cells next to a room are kept free so a corridor can always run between two rooms
+    let mut crowded: HashSet<glam::IVec2> = HashSet::new();
+    // generators grow out from start, so it can't be walled in by a room
+    crowded.insert(start);
+
+    for _ in 0..config.attempts {
+        let room = match random_room(layout, config, rng) {
+            Some(room) => room,
+            None => continue,
+        };
+        if room.cells.iter().any(|cell| crowded.contains(cell)) {
+            continue;
+        }
+        for cell in room.cells.iter() {
+            for y in -1..=1 {
+                for x in -1..=1 {
+                    crowded.insert(*cell + glam::ivec2(x, y));
+                }
+            }
+        }
+        carve_room(grid, layout, &room);
+        rooms.push(room);
+    }
+
+    rooms
+}
+
+fn flood(grid: &MazeGrid, from: glam::IVec2) -> HashSet<glam::IVec2> {
+    let mut reached: HashSet<glam::IVec2> = HashSet::new();
+    let mut stack: Vec<glam::IVec2> = vec![];
+    if grid.is_open(from) {
+        reached.insert(from);
+        stack.push(from);
+    }
+    while let Some(tile) = stack.pop() {
+        for offset in NEIGHBOR_OFFSETS {
+            let new_tile = tile + offset;
+            if grid.is_open(new_tile) && reached.insert(new_tile) {
+                stack.push(new_tile);
+            }
+        }
+    }
+    reached
+}
+
+pub fn connect_regions(
+    grid: &mut MazeGrid,
+    layout: &CellLayout,
+    start: glam::IVec2,
+    rng: &quad_rand::RandGenerator,
+) -> usize {
+    // opens doors until every open tile can be reached from start
+    // return: doors opened
+    let mut doors = 0;
+    loop {
+        let reached = flood(grid, layout.to_tile(start));
+        let region = match layout
+            .cells()
+            .map(|cell| layout.to_tile(cell))
+            .find(|tile| grid.is_open(*tile) && !reached.contains(tile))
+        {
+            Some(tile) => flood(grid, tile),
+            None => return doors,
+        };
+
+        let mut options: Vec<(glam::IVec2, glam::IVec2)> = vec![];
+        for cell in layout.cells() {
+            if !region.contains(&layout.to_tile(cell)) {
+                continue;
+            }
+            for new_cell in layout.neighbors(cell) {
+                if !region.contains(&layout.to_tile(new_cell))
+                    && !layout.passage_open(grid, cell, new_cell)
+                {
+                    options.push((cell, new_cell));
+                }
+            }
+        }
+        if options.is_empty() {
+            // the region fills the whole layout, nothing to connect it to
+            return doors;
+        }
+        // prefer a door straight onto the network, otherwise grow toward it
+        let best = options
+            .iter()
+            .copied()
+            .filter(|(_, new_cell)| reached.contains(&layout.to_tile(*new_cell)))
+            .collect::<Vec<(glam::IVec2, glam::IVec2)>>();
+        let options = if best.is_empty() { options } else { best };
+        let (cell, new_cell) = options[rng.gen_range(0, options.len())];
+        layout.carve_passage(grid, cell, new_cell);
+        doors += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::maze;
+
+    const SIZE: u32 = 41;
+
+    #[test]
+    fn rooms_stay_apart_and_inside() {
+        let layout = CellLayout::new(SIZE, glam::ivec2(21, 21));
+        let start = glam::ivec2(10, 10);
+        let config = RoomConfig::new(60, RoomOrder::BeforeMaze);
+        for seed in 0..20 {
+            let mut grid = MazeGrid::new(SIZE, SIZE, Tile::Wall);
+            let rooms = place_rooms(&mut grid, &layout, &config, start, &maze::create_rng(seed));
+            assert!(!rooms.is_empty());
+            for (i, room) in rooms.iter().enumerate() {
+                assert!(!room.contains(start));
+                for cell in room.cells.iter() {
+                    // off the outer ring of cells, so off the border tiles too
+                    assert!(cell.x >= 1 && cell.y >= 1);
+                    assert!(cell.x < layout.cols - 1 && cell.y < layout.rows - 1);
+                    assert!(grid.in_bounds(layout.to_tile(*cell)));
+                    for other in rooms[i + 1..].iter() {
+                        assert!(
+                            !other.contains(*cell),
+                            "seed {} has rooms overlapping",
+                            seed
+                        );
+                    }
+                }
+            }
+            let room_tiles = grid.iter().filter(|(_, tile)| *tile == Tile::Room).count();
+            assert!(room_tiles >= rooms.iter().map(|room| room.cells.len()).sum::<usize>());
+        }
+    }
+
+    #[test]
+    fn connect_regions_reaches_every_floor_tile() {
+        let layout = CellLayout::new(SIZE, glam::ivec2(21, 21));
+        let start = glam::ivec2(10, 10);
+        for seed in 0..10 {
+            let rng = maze::create_rng(seed);
+            // every row of cells is a corridor of its own, and a room cuts across a few
+            let mut grid = MazeGrid::new(SIZE, SIZE, Tile::Wall);
+            for y in 0..layout.rows {
+                layout.carve_cell(&mut grid, glam::ivec2(0, y));
+                for x in 1..layout.cols {
+                    layout.carve_passage(&mut grid, glam::ivec2(x - 1, y), glam::ivec2(x, y));
+                }
+            }
+            let room = Room {
+                cells: rect_cells(glam::ivec2(14, 3), glam::ivec2(3, 3)).collect(),
+            };
+            carve_room(&mut grid, &layout, &room);
+
+            // the room joins three rows into one, and each door joins two regions
+            let regions = layout.rows as usize - 2;
+            assert_eq!(
+                connect_regions(&mut grid, &layout, start, &rng),
+                regions - 1
+            );
+            let reached = flood(&grid, layout.to_tile(start));
+            let open = grid.iter().filter(|(_, tile)| tile.is_open()).count();
+            assert_eq!(reached.len(), open);
+        }
+    }
+}
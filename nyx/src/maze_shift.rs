@@ -0,0 +1,220 @@
+use crate::maze::{pick_sorted, Tile, NEIGHBOR_OFFSETS};
+use crate::maze_chunks::ChunkedMaze;
+use crate::maze_doors::Locked;
+use crate::maze_paths::{DistanceField, Walkable};
+
+use std::collections::HashSet;
+
+#[derive(PartialEq, Eq, Debug, Copy, Clone)]
+pub struct WallShift {
+    // a DistanceField can catch up with DistanceField::walls_closed and walls_opened
+    pub closed: glam::IVec2,
+    pub opened: glam::IVec2,
+}
+
+fn passage_cells(tile: glam::IVec2) -> Option<(glam::IVec2, glam::IVec2)> {
+    // cells sit on tiles with both coordinates odd, see maze_chunks::chunk_layout
+    match (tile.x.rem_euclid(2), tile.y.rem_euclid(2)) {
+        (1, 0) => Some((tile - glam::ivec2(0, 1), tile + glam::ivec2(0, 1))),
+        (0, 1) => Some((tile - glam::ivec2(1, 0), tile + glam::ivec2(1, 0))),
+        _ => None,
+    }
+}
+
+pub fn shift_wall(
+    maze: &mut ChunkedMaze,
+    keep_clear: (glam::IVec2, f32),
+    reach: i32,
+    doors: &[glam::IVec2],
+    protected: &[glam::IVec2],
+    rng: &quad_rand::RandGenerator,
+) -> Option<WallShift> {
+    // closes a loaded passage and opens a wall up to reach tiles away that joins the two sides
+    // back up, so everything that was reachable still is and nothing gets past a closed door
+    // keep_clear: (tile, radius in tiles) where nothing changes, like the player's light
+    // return: None when there was nothing that could move
+    let (clear_tile, clear_radius) = keep_clear;
+    let movable = |tile: glam::IVec2| {
+        (tile - clear_tile).as_vec2().length() > clear_radius
+            && !doors.contains(&tile)
+            && !protected.contains(&tile)
+    };
+    let between_open = |maze: &ChunkedMaze, tile: glam::IVec2| {
+        // and nothing else, a --maze file can have a passage with a side corridor off it that
+        // closing would cut off, or that opening would join past a door
+        passage_cells(tile)
+            .filter(|(a, b)| maze.is_open(*a) && maze.is_open(*b))
+            .filter(|_| {
+                NEIGHBOR_OFFSETS
+                    .iter()
+                    .filter(|offset| maze.is_open(tile + **offset))
+                    .count()
+                    == 2
+            })
+    };
+
+    // room floor isn't a passage, only corridors shift
+    let passages = maze
+        .chunks()
+        .flat_map(|(chunk_pt, chunk)| {
+            let min = *chunk_pt * maze.chunk_size;
+            chunk
+                .grid
+                .iter()
+                .filter(|(_, tile)| *tile == Tile::Floor)
+                .map(move |(local, _)| min + local)
+        })
+        .filter(|tile| movable(*tile) && between_open(maze, *tile).is_some())
+        .collect::<Vec<glam::IVec2>>();
+    let closed = pick_sorted(passages, rng)?;
+    let (a, b) = between_open(maze, closed).unwrap();
+
+    // doors count as walls so the new passage can't lead around one
+    let mut a_side = DistanceField::new(
+        &Locked {
+            maze: &*maze,
+            doors,
+        },
+        a,
+    );
+    maze.set(closed, Tile::Wall);
+    let locked = Locked {
+        maze: &*maze,
+        doors,
+    };
+    // whatever the a side lost can only be reached through b now
+    let cut_off = a_side
+        .walls_closed(&locked, &[closed])
+        .into_iter()
+        .collect::<HashSet<glam::IVec2>>();
+    let b_side = |tile: glam::IVec2| {
+        if a_side.get(b).is_some() {
+            a_side.get(tile).is_some()
+        } else {
+            cut_off.contains(&tile)
+        }
+    };
+    let mut walls = vec![];
+    for y in -reach..=reach {
+        for x in -reach..=reach {
+            let tile = closed + glam::ivec2(x, y);
+            if tile == closed || !maze.is_wall(tile) || !movable(tile) {
+                continue;
+            }
+            if let Some((c, d)) = between_open(maze, tile) {
+                let joins = |c: glam::IVec2, d: glam::IVec2| a_side.get(c).is_some() && b_side(d);
+                if joins(c, d) || joins(d, c) {
+                    walls.push(tile);
+                }
+            }
+        }
+    }
+
+    if walls.is_empty() {
+        maze.set(closed, Tile::Floor);
+        return None;
+    }
+    let opened = walls[rng.gen_range(0, walls.len())];
+    maze.set(opened, Tile::Floor);
+    Some(WallShift { closed, opened })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::maze_braid::BraidConfig;
+    use crate::maze_generators;
+    use crate::maze_io;
+    use crate::maze_rooms::RoomConfig;
+
+    // (3, 2) sits between two cells like a passage, but the corridor to its left hangs off it
+    const SIDE_CORRIDOR: &str = "\
+#######
+###...#
+#...#.#
+###.#.#
+###...#
+#######
+";
+
+    // hand drawn, so passages and rooms go wherever
+    const AUTHORED: &str = "\
+###############
+#.....#.......#
+#.###.#.#####.#
+#.#.....#...#.#
+#.#.###.#.#.#.#
+#...#_____#...#
+###.#_____###.#
+#...#_____....#
+#.###.#####.#.#
+#.....#.....#.#
+###############
+";
+
+    fn assert_all_reachable(maze: &ChunkedMaze, start: glam::IVec2, context: &str) {
+        let open = maze
+            .chunks()
+            .flat_map(|(chunk_pt, chunk)| {
+                let min = *chunk_pt * maze.chunk_size;
+                chunk
+                    .grid
+                    .iter()
+                    .filter(|(_, tile)| tile.is_open())
+                    .map(move |(local, _)| min + local)
+            })
+            .count();
+        let field = DistanceField::new(maze, start);
+        assert_eq!(field.len(), open, "{}", context);
+    }
+
+    fn shift_many(mut maze: ChunkedMaze, start: glam::IVec2, seeds: u64) -> usize {
+        // return: how many shifts there were
+        let mut shifts = 0;
+        for seed in 0..seeds {
+            let rng = crate::maze::create_rng(seed);
+            for i in 0..20 {
+                let shift = shift_wall(&mut maze, (start, 0.), 4, &[], &[start], &rng);
+                shifts += shift.is_some() as usize;
+                assert_all_reachable(&maze, start, &format!("seed {} shift {}", seed, i));
+            }
+        }
+        shifts
+    }
+
+    fn authored(ascii: &str) -> ChunkedMaze {
+        let grid = maze_io::from_ascii(ascii).unwrap();
+        let mut maze = ChunkedMaze::from_grid(grid, 16);
+        maze.update(glam::ivec2(0, 0), 1, 1);
+        maze
+    }
+
+    #[test]
+    fn side_corridors_are_left_alone() {
+        let mut maze = authored(SIDE_CORRIDOR);
+        let rng = crate::maze::create_rng(6);
+        for _ in 0..20 {
+            if let Some(shift) = shift_wall(&mut maze, (glam::ivec2(5, 1), 0.), 4, &[], &[], &rng) {
+                assert_ne!(shift.closed, glam::ivec2(3, 2));
+            }
+            assert_all_reachable(&maze, glam::ivec2(5, 1), "side corridor");
+        }
+    }
+
+    #[test]
+    fn shifting_keeps_every_floor_tile_reachable() {
+        assert!(shift_many(authored(AUTHORED), glam::ivec2(1, 1), 10) > 0);
+
+        let mut maze = ChunkedMaze::new(
+            3,
+            16,
+            maze_generators::parse_generator("backtracker").unwrap(),
+            BraidConfig::new(0.5, 0.1),
+            RoomConfig::default(),
+        );
+        for chunk in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+            maze.load(chunk.into());
+        }
+        assert!(shift_many(maze, glam::ivec2(9, 9), 10) > 0);
+    }
+}
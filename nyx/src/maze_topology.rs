@@ -0,0 +1,509 @@
+use crate::maze::{CellLayout, MazeGrid};
+
+use std::collections::{HashSet, VecDeque};
+use std::f32::consts::{PI, TAU};
+
+pub type Segment = (glam::Vec2, glam::Vec2);
+
+pub trait Topology {
+    // cells are numbered 0..len, what they look like is up to the topology
+    fn len(&self) -> usize;
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    fn neighbors(&self, cell: usize) -> Vec<usize>;
+    fn is_carved(&self, cell: usize) -> bool;
+    fn carve_cell(&mut self, cell: usize);
+    fn carve_passage(&mut self, a: usize, b: usize);
+}
+
+pub struct SquareTopology<'a> {
+    pub grid: &'a mut MazeGrid,
+    pub layout: &'a CellLayout,
+}
+impl Topology for SquareTopology<'_> {
+    fn len(&self) -> usize {
+        self.layout.len()
+    }
+    fn neighbors(&self, cell: usize) -> Vec<usize> {
+        self.layout
+            .neighbors(self.layout.cell_at(cell))
+            .map(|new_cell| self.layout.idx(new_cell))
+            .collect()
+    }
+    fn is_carved(&self, cell: usize) -> bool {
+        self.layout.is_carved(self.grid, self.layout.cell_at(cell))
+    }
+    fn carve_cell(&mut self, cell: usize) {
+        self.layout.carve_cell(self.grid, self.layout.cell_at(cell));
+    }
+    fn carve_passage(&mut self, a: usize, b: usize) {
+        self.layout
+            .carve_passage(self.grid, self.layout.cell_at(a), self.layout.cell_at(b));
+    }
+}
+
+pub trait Shape {
+    // the geometry of a topology that doesn't sit on tiles, in world px around (0, 0)
+    fn name(&self) -> String;
+    fn len(&self) -> usize;
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    fn neighbors(&self, cell: usize) -> Vec<usize>;
+    fn center(&self, cell: usize) -> glam::Vec2;
+    fn cell_at(&self, pt: glam::Vec2) -> Option<usize>;
+    fn start(&self) -> usize;
+    // the wall between two neighboring cells
+    fn edge(&self, a: usize, b: usize) -> Vec<Segment>;
+    // walls around the outside, with the cell each one belongs to
+    fn boundary(&self) -> Vec<(usize, Segment)>;
+}
+
+fn arc(center: glam::Vec2, radius: f32, from: f32, to: f32) -> Vec<Segment> {
+    // straight pieces no longer than about 10 degrees
+    let steps = ((to - from).abs() / (PI / 18.)).ceil().max(1.) as usize;
+    let pt = |i: usize| {
+        let angle = from + (to - from) * i as f32 / steps as f32;
+        center + glam::Vec2::from_angle(angle) * radius
+    };
+    (0..steps).map(|i| (pt(i), pt(i + 1))).collect()
+}
+
+pub struct HexShape {
+    pub cols: i32,
+    pub rows: i32,
+    pub radius: f32, // center to corner, the hexes are pointy topped
+}
+impl HexShape {
+    pub fn new(cols: i32, rows: i32, radius: f32) -> HexShape {
+        HexShape { cols, rows, radius }
+    }
+    fn to_cell(&self, idx: usize) -> glam::IVec2 {
+        glam::ivec2(idx as i32 % self.cols, idx as i32 / self.cols)
+    }
+    fn contains(&self, cell: glam::IVec2) -> bool {
+        cell.x >= 0 && cell.y >= 0 && cell.x < self.cols && cell.y < self.rows
+    }
+    fn offsets(row: i32) -> [glam::IVec2; 6] {
+        // odd rows are pushed half a hex to the right
+        let shift = row.rem_euclid(2);
+        [
+            glam::ivec2(1, 0),
+            glam::ivec2(shift, 1),
+            glam::ivec2(shift - 1, 1),
+            glam::ivec2(-1, 0),
+            glam::ivec2(shift - 1, -1),
+            glam::ivec2(shift, -1),
+        ]
+    }
+    fn side(&self, cell: usize, dir: usize) -> Segment {
+        // dir matches offsets, side 0 faces east and they go clockwise (y is down)
+        let center = self.center(cell);
+        let corner = |angle: f32| center + glam::Vec2::from_angle(angle) * self.radius;
+        let angle = dir as f32 * PI / 3.;
+        (corner(angle - PI / 6.), corner(angle + PI / 6.))
+    }
+}
+impl Shape for HexShape {
+    fn name(&self) -> String {
+        "hex".to_owned()
+    }
+    fn len(&self) -> usize {
+        (self.cols * self.rows) as usize
+    }
+    fn neighbors(&self, cell: usize) -> Vec<usize> {
+        let pos = self.to_cell(cell);
+        HexShape::offsets(pos.y)
+            .iter()
+            .map(|offset| pos + *offset)
+            .filter(|new_pos| self.contains(*new_pos))
+            .map(|new_pos| (new_pos.y * self.cols + new_pos.x) as usize)
+            .collect()
+    }
+    fn center(&self, cell: usize) -> glam::Vec2 {
+        let pos = self.to_cell(cell);
+        let w = 3f32.sqrt() * self.radius;
+        glam::vec2(
+            w * (pos.x as f32 + 0.5 * pos.y.rem_euclid(2) as f32),
+            1.5 * self.radius * pos.y as f32,
+        )
+    }
+    fn cell_at(&self, pt: glam::Vec2) -> Option<usize> {
+        // the closest center is the hex pt is in
+        let row = (pt.y / (1.5 * self.radius)).round() as i32;
+        let col = (pt.x / (3f32.sqrt() * self.radius)).round() as i32;
+        let mut best: Option<(f32, usize)> = None;
+        for y in row - 1..=row + 1 {
+            for x in col - 1..=col + 1 {
+                let pos = glam::ivec2(x, y);
+                if !self.contains(pos) {
+                    continue;
+                }
+                let cell = (y * self.cols + x) as usize;
+                let d = self.center(cell).distance(pt);
+                if d <= self.radius && best.is_none_or(|(best_d, _)| d < best_d) {
+                    best = Some((d, cell));
+                }
+            }
+        }
+        best.map(|(_, cell)| cell)
+    }
+    fn start(&self) -> usize {
+        (self.rows / 2 * self.cols + self.cols / 2) as usize
+    }
+    fn edge(&self, a: usize, b: usize) -> Vec<Segment> {
+        let (pos_a, pos_b) = (self.to_cell(a), self.to_cell(b));
+        HexShape::offsets(pos_a.y)
+            .iter()
+            .position(|offset| pos_a + *offset == pos_b)
+            .map(|dir| vec![self.side(a, dir)])
+            .unwrap_or_default()
+    }
+    fn boundary(&self) -> Vec<(usize, Segment)> {
+        let mut walls = vec![];
+        for cell in 0..self.len() {
+            let pos = self.to_cell(cell);
+            for (dir, offset) in HexShape::offsets(pos.y).iter().enumerate() {
+                if !self.contains(pos + *offset) {
+                    walls.push((cell, self.side(cell, dir)));
+                }
+            }
+        }
+        walls
+    }
+}
+
+pub struct PolarShape {
+    pub ring_width: f32,
+    ring_sizes: Vec<usize>, // cells in each ring, the middle ring is one cell
+    ring_starts: Vec<usize>,
+}
+impl PolarShape {
+    pub fn new(rings: usize, ring_width: f32) -> PolarShape {
+        // a ring splits its cells in two whenever they'd get much wider than they are tall
+        let mut ring_sizes = vec![1];
+        for ring in 1..rings {
+            let last = ring_sizes[ring - 1];
+            let cell_width = TAU * ring as f32 / last as f32;
+            ring_sizes.push(last * cell_width.round().max(1.) as usize);
+        }
+        let ring_starts = ring_sizes
+            .iter()
+            .scan(0, |start, size| {
+                let ring_start = *start;
+                *start += size;
+                Some(ring_start)
+            })
+            .collect();
+        PolarShape {
+            ring_width,
+            ring_sizes,
+            ring_starts,
+        }
+    }
+    pub fn rings(&self) -> usize {
+        self.ring_sizes.len()
+    }
+    fn to_cell(&self, idx: usize) -> (usize, usize) {
+        // return: (ring, position around the ring)
+        let ring = self.ring_starts.partition_point(|start| *start <= idx) - 1;
+        (ring, idx - self.ring_starts[ring])
+    }
+    fn idx(&self, ring: usize, i: usize) -> usize {
+        self.ring_starts[ring] + i % self.ring_sizes[ring]
+    }
+    fn angles(&self, ring: usize, i: usize) -> (f32, f32) {
+        let step = TAU / self.ring_sizes[ring] as f32;
+        (i as f32 * step, (i + 1) as f32 * step)
+    }
+}
+impl Shape for PolarShape {
+    fn name(&self) -> String {
+        "polar".to_owned()
+    }
+    fn len(&self) -> usize {
+        self.ring_sizes.iter().sum()
+    }
+    fn neighbors(&self, cell: usize) -> Vec<usize> {
+        let (ring, i) = self.to_cell(cell);
+        let mut cells = vec![];
+        if ring > 0 {
+            let size = self.ring_sizes[ring];
+            cells.push(self.idx(ring, i + 1));
+            cells.push(self.idx(ring, i + size - 1));
+            let ratio = size / self.ring_sizes[ring - 1];
+            cells.push(self.idx(ring - 1, i / ratio));
+        }
+        if ring + 1 < self.rings() {
+            let ratio = self.ring_sizes[ring + 1] / self.ring_sizes[ring];
+            cells.extend((0..ratio).map(|j| self.idx(ring + 1, i * ratio + j)));
+        }
+        cells
+    }
+    fn center(&self, cell: usize) -> glam::Vec2 {
+        let (ring, i) = self.to_cell(cell);
+        if ring == 0 {
+            return glam::Vec2::ZERO;
+        }
+        let (from, to) = self.angles(ring, i);
+        glam::Vec2::from_angle((from + to) / 2.) * (ring as f32 + 0.5) * self.ring_width
+    }
+    fn cell_at(&self, pt: glam::Vec2) -> Option<usize> {
+        let ring = (pt.length() / self.ring_width) as usize;
+        if ring >= self.rings() {
+            return None;
+        }
+        let angle = pt.y.atan2(pt.x).rem_euclid(TAU);
+        let i = (angle / TAU * self.ring_sizes[ring] as f32) as usize;
+        Some(self.idx(ring, i))
+    }
+    fn start(&self) -> usize {
+        0
+    }
+    fn edge(&self, a: usize, b: usize) -> Vec<Segment> {
+        let ((ring_a, i_a), (ring_b, i_b)) = (self.to_cell(a), self.to_cell(b));
+        if ring_a == ring_b {
+            // a spoke at whichever end they share
+            let (from, to) = self.angles(ring_a, i_a);
+            let angle = if self.idx(ring_a, i_a + 1) == b {
+                to
+            } else {
+                from
+            };
+            let dir = glam::Vec2::from_angle(angle);
+            vec![(
+                dir * ring_a as f32 * self.ring_width,
+                dir * (ring_a + 1) as f32 * self.ring_width,
+            )]
+        } else {
+            // the inner arc of the outer cell
+            let (outer, i) = if ring_a > ring_b {
+                (ring_a, i_a)
+            } else {
+                (ring_b, i_b)
+            };
+            let (from, to) = self.angles(outer, i);
+            arc(glam::Vec2::ZERO, outer as f32 * self.ring_width, from, to)
+        }
+    }
+    fn boundary(&self) -> Vec<(usize, Segment)> {
+        let ring = self.rings() - 1;
+        (0..self.ring_sizes[ring])
+            .flat_map(|i| {
+                let (from, to) = self.angles(ring, i);
+                let radius = self.rings() as f32 * self.ring_width;
+                arc(glam::Vec2::ZERO, radius, from, to)
+                    .into_iter()
+                    .map(move |segment| (self.idx(ring, i), segment))
+            })
+            .collect()
+    }
+}
+
+pub struct ShapedMaze {
+    pub shape: Box<dyn Shape>,
+    carved: Vec<bool>,
+    passages: HashSet<(usize, usize)>, // (low, high) cell pairs
+}
+impl ShapedMaze {
+    pub fn new(shape: Box<dyn Shape>) -> ShapedMaze {
+        ShapedMaze {
+            carved: vec![false; shape.len()],
+            shape,
+            passages: HashSet::new(),
+        }
+    }
+    pub fn passage_open(&self, a: usize, b: usize) -> bool {
+        self.passages.contains(&(a.min(b), a.max(b)))
+    }
+    pub fn walls(&self) -> Vec<Segment> {
+        let mut walls = self
+            .shape
+            .boundary()
+            .into_iter()
+            .map(|(_, segment)| segment)
+            .collect::<Vec<Segment>>();
+        for cell in 0..self.len() {
+            for new_cell in self.neighbors(cell) {
+                // each wall once, from its lower cell
+                if cell < new_cell && !self.passage_open(cell, new_cell) {
+                    walls.extend(self.shape.edge(cell, new_cell));
+                }
+            }
+        }
+        walls
+    }
+    pub fn distances(&self, from: usize) -> Vec<Option<u32>> {
+        let mut distances = vec![None; self.len()];
+        distances[from] = Some(0);
+        let mut queue = VecDeque::from(vec![from]);
+        while let Some(cell) = queue.pop_front() {
+            let d = distances[cell].unwrap() + 1;
+            for new_cell in self.neighbors(cell) {
+                if distances[new_cell].is_none() && self.passage_open(cell, new_cell) {
+                    distances[new_cell] = Some(d);
+                    queue.push_back(new_cell);
+                }
+            }
+        }
+        distances
+    }
+    pub fn route_length(&self, from: usize, to: usize) -> Option<f32> {
+        // return: px from center to center along the shortest way between the two cells
+        let distances = self.distances(to);
+        let mut d = distances[from]?;
+        let mut cell = from;
+        let mut length = 0.;
+        while d > 0 {
+            let next = self.neighbors(cell).into_iter().find(|new_cell| {
+                distances[*new_cell] == Some(d - 1) && self.passage_open(cell, *new_cell)
+            })?;
+            length += self.shape.center(cell).distance(self.shape.center(next));
+            cell = next;
+            d -= 1;
+        }
+        Some(length)
+    }
+    pub fn edge_cells(&self) -> Vec<usize> {
+        let mut cells = self
+            .shape
+            .boundary()
+            .into_iter()
+            .map(|(cell, _)| cell)
+            .collect::<Vec<usize>>();
+        cells.dedup();
+        cells
+    }
+}
+impl Topology for ShapedMaze {
+    fn len(&self) -> usize {
+        self.carved.len()
+    }
+    fn neighbors(&self, cell: usize) -> Vec<usize> {
+        self.shape.neighbors(cell)
+    }
+    fn is_carved(&self, cell: usize) -> bool {
+        self.carved[cell]
+    }
+    fn carve_cell(&mut self, cell: usize) {
+        self.carved[cell] = true;
+    }
+    fn carve_passage(&mut self, a: usize, b: usize) {
+        self.carved[a] = true;
+        self.carved[b] = true;
+        self.passages.insert((a.min(b), a.max(b)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::maze::{self, Tile};
+    use crate::maze_generators;
+
+    struct Recorded<'a> {
+        // passes everything through and keeps each passage carved
+        topology: &'a mut dyn Topology,
+        passages: Vec<(usize, usize)>,
+    }
+    impl Topology for Recorded<'_> {
+        fn len(&self) -> usize {
+            self.topology.len()
+        }
+        fn neighbors(&self, cell: usize) -> Vec<usize> {
+            self.topology.neighbors(cell)
+        }
+        fn is_carved(&self, cell: usize) -> bool {
+            self.topology.is_carved(cell)
+        }
+        fn carve_cell(&mut self, cell: usize) {
+            self.topology.carve_cell(cell);
+        }
+        fn carve_passage(&mut self, a: usize, b: usize) {
+            self.passages.push((a, b));
+            self.topology.carve_passage(a, b);
+        }
+    }
+
+    fn assert_neighbors_agree(topology: &dyn Topology) {
+        for cell in 0..topology.len() {
+            for new_cell in topology.neighbors(cell) {
+                assert_ne!(cell, new_cell);
+                assert!(
+                    topology.neighbors(new_cell).contains(&cell),
+                    "{} is next to {} but not the other way",
+                    cell,
+                    new_cell
+                );
+            }
+        }
+    }
+
+    fn assert_spanning_tree(topology: &mut dyn Topology, start: usize) {
+        let len = topology.len();
+        let mut recorded = Recorded {
+            topology,
+            passages: vec![],
+        };
+        maze_generators::backtrack(&mut recorded, start, &maze::create_rng(5));
+        assert!((0..len).all(|cell| recorded.is_carved(cell)));
+        // one passage fewer than cells, and all joined up, is a tree
+        assert_eq!(recorded.passages.len(), len - 1);
+        let mut reached = vec![false; len];
+        reached[start] = true;
+        let mut queue = VecDeque::from(vec![start]);
+        while let Some(cell) = queue.pop_front() {
+            for (a, b) in recorded.passages.iter() {
+                assert!(recorded.neighbors(*a).contains(b));
+                let new_cell = match (*a == cell, *b == cell) {
+                    (true, _) => *b,
+                    (_, true) => *a,
+                    _ => continue,
+                };
+                if !reached[new_cell] {
+                    reached[new_cell] = true;
+                    queue.push_back(new_cell);
+                }
+            }
+        }
+        assert!(reached.iter().all(|reached| *reached));
+    }
+
+    #[test]
+    fn neighbors_go_both_ways() {
+        let mut grid = MazeGrid::new(15, 11, Tile::Wall);
+        let layout = CellLayout::new(15, glam::ivec2(7, 5));
+        assert_neighbors_agree(&SquareTopology {
+            grid: &mut grid,
+            layout: &layout,
+        });
+        assert_neighbors_agree(&ShapedMaze::new(Box::new(HexShape::new(7, 6, 10.))));
+        assert_neighbors_agree(&ShapedMaze::new(Box::new(PolarShape::new(6, 10.))));
+    }
+
+    #[test]
+    fn the_backtracker_makes_a_spanning_tree() {
+        let mut grid = MazeGrid::new(15, 15, Tile::Wall);
+        let layout = CellLayout::new(15, glam::ivec2(7, 7));
+        let start = layout.idx(glam::ivec2(3, 3));
+        assert_spanning_tree(
+            &mut SquareTopology {
+                grid: &mut grid,
+                layout: &layout,
+            },
+            start,
+        );
+
+        for shape in [
+            Box::new(HexShape::new(7, 6, 10.)) as Box<dyn Shape>,
+            Box::new(PolarShape::new(6, 10.)),
+        ] {
+            let start = shape.start();
+            let mut maze = ShapedMaze::new(shape);
+            assert_spanning_tree(&mut maze, start);
+            let distances = maze.distances(start);
+            assert!(distances.iter().all(|d| d.is_some()));
+        }
+    }
+}
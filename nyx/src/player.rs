@@ -36,6 +36,8 @@ pub struct Player {
 
     pub last_dir: DirKey,
     pub wall_dirs: Vec<DirKey>,
+
+    pub held_keys: Vec<usize>, // which locked doors the player can open
 }
 impl Player {
     pub fn new(pt: mq::Vec2, w: f32, h: f32, light: Light) -> Player {
@@ -51,6 +53,7 @@ impl Player {
             right_tk: ToggleKey::new(),
             last_dir: DirKey::Right,
             wall_dirs: vec![],
+            held_keys: vec![],
         }
     }
     pub fn update_light_pt(&mut self) {
@@ -118,7 +121,8 @@ impl Player {
             (true, self.last_dir)
         } else {
             for k in self.keys.iter().rev() {
-                if !self.keys.contains(&k.opposite()) { //&& !self.wall_dirs.contains(k) {
+                if !self.keys.contains(&k.opposite()) {
+                    //&& !self.wall_dirs.contains(k) {
                     self.last_dir = *k;
                     return (false, *k);
                 }
@@ -184,21 +188,41 @@ impl Player {
                 move_vec.x = other.x + other.w - rect.x;
             }
 
-            let dir = self.calc_dir();
-            match dir {
-                (false, k) => {
-                    if self.keys.contains(&k) && !self.wall_dirs.contains(&k) {
-                        self.wall_dirs.push(k);
-                    }
-                }
-                (true, _) => {}
-            }
-
-            self.pt += move_vec;
-            cm.pt += move_vec;
+            self.push_out(cm, move_vec);
             true
         } else {
             false
         }
     }
+    pub fn collide_segment(
+        &mut self,
+        cm: &mut CameraManager,
+        a: mq::Vec2,
+        b: mq::Vec2,
+        thickness: f32,
+    ) -> bool {
+        // the player is treated as a circle so it slides along slanted walls
+        let center = self.pt + mq::vec2(self.w, self.h) / 2.;
+        let radius = self.w.max(self.h) / 2. + thickness / 2.;
+        let ab = b - a;
+        let t = ((center - a).dot(ab) / ab.length_squared()).clamp(0., 1.);
+        let d = center - (a + ab * t);
+        let dist = d.length();
+        if dist < radius && dist > 0. {
+            self.push_out(cm, d / dist * (radius - dist));
+            true
+        } else {
+            false
+        }
+    }
+    fn push_out(&mut self, cm: &mut CameraManager, move_vec: mq::Vec2) {
+        if let (false, k) = self.calc_dir() {
+            if self.keys.contains(&k) && !self.wall_dirs.contains(&k) {
+                self.wall_dirs.push(k);
+            }
+        }
+
+        self.pt += move_vec;
+        cm.pt += move_vec;
+    }
 }
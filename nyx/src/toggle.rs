@@ -1,9 +1,10 @@
+#[derive(Default)]
 pub struct ToggleKey {
     was_down: bool,
 }
 impl ToggleKey {
     pub fn new() -> Self {
-        Self { was_down: false }
+        Self::default()
     }
     pub fn down(&mut self, state: bool) -> bool {
         if !self.was_down && state {
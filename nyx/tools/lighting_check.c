@@ -0,0 +1,198 @@
+// renders the cases lighting::tests::write_llvmpipe_cases writes with the real shader on a
+// surfaceless egl context, and compares every pixel with the cpu reference
+// usage: lighting_check <dir>, see lighting_check.sh
+#include <EGL/egl.h>
+#include <EGL/eglext.h>
+#include <GLES2/gl2.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+// the same size as the reference in lighting.rs
+#define WIDTH 256
+#define HEIGHT 144
+
+static const char *dir;
+
+static char *slurp(const char *file) {
+    char path[4096];
+    snprintf(path, sizeof(path), "%s/%s", dir, file);
+    FILE *f = fopen(path, "rb");
+    if (!f) {
+        perror(path);
+        exit(2);
+    }
+    fseek(f, 0, SEEK_END);
+    long len = ftell(f);
+    rewind(f);
+    char *bytes = malloc(len + 1);
+    if (fread(bytes, 1, len, f) != (size_t)len) {
+        perror(path);
+        exit(2);
+    }
+    bytes[len] = 0;
+    fclose(f);
+    return bytes;
+}
+
+static GLuint compile(GLenum type, const char *file) {
+    const char *src = slurp(file);
+    GLuint shader = glCreateShader(type);
+    glShaderSource(shader, 1, &src, 0);
+    glCompileShader(shader);
+    GLint ok;
+    char log[4096] = "";
+    glGetShaderiv(shader, GL_COMPILE_STATUS, &ok);
+    glGetShaderInfoLog(shader, sizeof(log), 0, log);
+    if (!ok || log[0]) {
+        printf("%s: %s\n", file, log);
+    }
+    if (!ok) {
+        exit(2);
+    }
+    return shader;
+}
+
+static GLuint texture(GLenum unit, int width, int height, const char *file) {
+    GLuint tex;
+    glActiveTexture(unit);
+    glGenTextures(1, &tex);
+    glBindTexture(GL_TEXTURE_2D, tex);
+    glTexParameteri(GL_TEXTURE_2D, GL_TEXTURE_MIN_FILTER, GL_NEAREST);
+    glTexParameteri(GL_TEXTURE_2D, GL_TEXTURE_MAG_FILTER, GL_NEAREST);
+    glTexParameteri(GL_TEXTURE_2D, GL_TEXTURE_WRAP_S, GL_CLAMP_TO_EDGE);
+    glTexParameteri(GL_TEXTURE_2D, GL_TEXTURE_WRAP_T, GL_CLAMP_TO_EDGE);
+    glTexImage2D(GL_TEXTURE_2D, 0, GL_RGBA, width, height, 0, GL_RGBA, GL_UNSIGNED_BYTE,
+                 file ? slurp(file) : 0);
+    glActiveTexture(GL_TEXTURE0);
+    return tex;
+}
+
+int main(int argc, char **argv) {
+    if (argc != 2) {
+        printf("usage: lighting_check <dir>\n");
+        return 2;
+    }
+    dir = argv[1];
+
+    PFNEGLGETPLATFORMDISPLAYEXTPROC get_display =
+        (PFNEGLGETPLATFORMDISPLAYEXTPROC)eglGetProcAddress("eglGetPlatformDisplayEXT");
+    EGLDisplay display =
+        get_display ? get_display(EGL_PLATFORM_SURFACELESS_MESA, EGL_DEFAULT_DISPLAY, 0) : 0;
+    if (!display || !eglInitialize(display, 0, 0)) {
+        printf("no surfaceless egl display\n");
+        return 2;
+    }
+    eglBindAPI(EGL_OPENGL_ES_API);
+    EGLint config_attribs[] = {EGL_RENDERABLE_TYPE, EGL_OPENGL_ES2_BIT, EGL_NONE};
+    EGLConfig config;
+    EGLint configs;
+    eglChooseConfig(display, config_attribs, &config, 1, &configs);
+    EGLint context_attribs[] = {EGL_CONTEXT_CLIENT_VERSION, 2, EGL_NONE};
+    EGLContext context =
+        eglCreateContext(display, configs ? config : 0, EGL_NO_CONTEXT, context_attribs);
+    if (!eglMakeCurrent(display, EGL_NO_SURFACE, EGL_NO_SURFACE, context)) {
+        printf("can't make a gles2 context current\n");
+        return 2;
+    }
+    printf("%s: %s, %s\n", dir, glGetString(GL_RENDERER), glGetString(GL_VERSION));
+
+    GLuint program = glCreateProgram();
+    glAttachShader(program, compile(GL_VERTEX_SHADER, "vert.glsl"));
+    glAttachShader(program, compile(GL_FRAGMENT_SHADER, "frag.glsl"));
+    glBindAttribLocation(program, 0, "position");
+    glBindAttribLocation(program, 1, "texcoord");
+    glLinkProgram(program);
+    GLint ok;
+    glGetProgramiv(program, GL_LINK_STATUS, &ok);
+    if (!ok) {
+        printf("the shaders don't link\n");
+        return 2;
+    }
+    glUseProgram(program);
+    float identity[16] = {1, 0, 0, 0, 0, 1, 0, 0, 0, 0, 1, 0, 0, 0, 0, 1};
+    glUniformMatrix4fv(glGetUniformLocation(program, "Model"), 1, 0, identity);
+    glUniformMatrix4fv(glGetUniformLocation(program, "Projection"), 1, 0, identity);
+    glUniform2f(glGetUniformLocation(program, "screen_size"), WIDTH, HEIGHT);
+
+    GLuint screen = texture(GL_TEXTURE0, WIDTH, HEIGHT, "screen.rgba");
+    texture(GL_TEXTURE1, 4, 4, "dither.rgba");
+    GLuint mask = texture(GL_TEXTURE2, 1, 1, 0);
+    glUniform1i(glGetUniformLocation(program, "Texture"), 0);
+    glUniform1i(glGetUniformLocation(program, "dither"), 1);
+    glUniform1i(glGetUniformLocation(program, "light_mask"), 2);
+
+    GLuint out = texture(GL_TEXTURE0, WIDTH, HEIGHT, 0);
+    GLuint framebuffer;
+    glGenFramebuffers(1, &framebuffer);
+    glBindFramebuffer(GL_FRAMEBUFFER, framebuffer);
+    glFramebufferTexture2D(GL_FRAMEBUFFER, GL_COLOR_ATTACHMENT0, GL_TEXTURE_2D, out, 0);
+    glBindTexture(GL_TEXTURE_2D, screen);
+    glViewport(0, 0, WIDTH, HEIGHT);
+
+    // drawn with flip_y like Lighting::draw, the top of the quad samples v = 1
+    float verts[] = {-1, 1, 0, 0, 1, 1, 1, 0, 1, 1, 1, -1, 0, 1, 0, -1, -1, 0, 0, 0};
+    glVertexAttribPointer(0, 3, GL_FLOAT, 0, 20, verts);
+    glVertexAttribPointer(1, 2, GL_FLOAT, 0, 20, verts + 3);
+    glEnableVertexAttribArray(0);
+    glEnableVertexAttribArray(1);
+
+    char *cases = slurp("cases.txt");
+    int failed = 0, lights, mask_width, mask_height, read;
+    float rect[4];
+    for (int k = 0; sscanf(cases, "%d %d %d %f %f %f %f%n", &lights, &mask_width, &mask_height,
+                           &rect[0], &rect[1], &rect[2], &rect[3], &read) == 7;
+         k++) {
+        cases += read;
+        char file[64];
+        snprintf(file, sizeof(file), "mask%d.rgba", k);
+        glActiveTexture(GL_TEXTURE2);
+        glBindTexture(GL_TEXTURE_2D, mask);
+        glTexImage2D(GL_TEXTURE_2D, 0, GL_RGBA, mask_width, mask_height, 0, GL_RGBA,
+                     GL_UNSIGNED_BYTE, slurp(file));
+        glActiveTexture(GL_TEXTURE0);
+        glUniform4f(glGetUniformLocation(program, "mask_rect"), rect[0], rect[1], rect[2], rect[3]);
+        glUniform1i(glGetUniformLocation(program, "light_count"), lights);
+        for (int i = 0; i < lights; i++) {
+            float l[8];
+            for (int j = 0; j < 8; j++) {
+                sscanf(cases, "%f%n", &l[j], &read);
+                cases += read;
+            }
+            char name[32];
+            snprintf(name, sizeof(name), "light_%d", i);
+            glUniform4f(glGetUniformLocation(program, name), l[0], l[1], l[2], l[3]);
+            snprintf(name, sizeof(name), "light_color_%d", i);
+            glUniform4f(glGetUniformLocation(program, name), l[4], l[5], l[6], l[7]);
+        }
+        glClearColor(1, 0, 1, 1);
+        glClear(GL_COLOR_BUFFER_BIT);
+        glDrawArrays(GL_TRIANGLE_FAN, 0, 4);
+        static unsigned char px[WIDTH * HEIGHT * 4];
+        glReadPixels(0, 0, WIDTH, HEIGHT, GL_RGBA, GL_UNSIGNED_BYTE, px);
+
+        // gl reads rows from the bottom, the reference is from the top
+        snprintf(file, sizeof(file), "expected%d.rgba", k);
+        unsigned char *expected = (unsigned char *)slurp(file);
+        int differ = 0, max_diff = 0;
+        for (int y = 0; y < HEIGHT; y++) {
+            for (int x = 0; x < WIDTH; x++) {
+                for (int ch = 0; ch < 4; ch++) {
+                    int gpu = px[((HEIGHT - 1 - y) * WIDTH + x) * 4 + ch];
+                    int cpu = expected[(y * WIDTH + x) * 4 + ch];
+                    int diff = abs(gpu - cpu);
+                    max_diff = diff > max_diff ? diff : max_diff;
+                    differ += diff != 0;
+                    // a byte either way is float rounding, anything more is a real difference
+                    if (diff > 1 && failed++ < 5) {
+                        printf("case %d (%d, %d) channel %d: gpu %d cpu %d\n", k, x, y, ch, gpu,
+                               cpu);
+                    }
+                }
+            }
+        }
+        printf("case %d: %d lights, %d channels differ, by at most %d\n", k, lights, differ,
+               max_diff);
+    }
+    return failed != 0;
+}
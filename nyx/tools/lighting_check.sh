@@ -0,0 +1,14 @@
+#!/bin/sh
+# compares the lighting shader run on mesa's llvmpipe with the cpu reference in lighting.rs
+# needs a c compiler, the egl and gles2 headers, and mesa
+# usage: tools/lighting_check.sh
+set -e
+cd "$(dirname "$0")/.."
+dir=$(mktemp -d)
+trap 'rm -rf "$dir"' EXIT
+
+${CC:-cc} -O2 -o "$dir/lighting_check" tools/lighting_check.c -lEGL -lGLESv2
+NYX_LIGHTING_CASES="$dir" cargo test --lib lighting::tests::write_llvmpipe_cases -- --ignored
+for tonemap in clamp reinhard; do
+    GALLIUM_DRIVER=llvmpipe LIBGL_ALWAYS_SOFTWARE=1 "$dir/lighting_check" "$dir/$tonemap"
+done